@@ -0,0 +1,102 @@
+use crate::board::Action;
+use crate::montecarlo::RootChild;
+
+// z of a two-sided 95% interval
+pub const DEFAULT_Z: f32 = 1.96;
+
+// one root move with a confidence interval around its win rate
+#[derive(Clone, Copy, Debug)]
+pub struct MoveAnalysis {
+    pub action: Action,
+    pub visits: i32,
+    // for the side to move at the root, 0.5 without visits
+    pub win_rate: f32,
+    // Wilson score interval of the win rate, 0 to 1 without visits
+    pub lower: f32,
+    pub upper: f32,
+}
+
+// What a search makes of the root's moves. Win rates from a few hundred
+// visits are noisy, so a tool can keep searching until `separated` says the
+// best move is clearly ahead instead of relying on a fixed budget.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    // most visited first
+    pub moves: Vec<MoveAnalysis>,
+    pub best: Action,
+    // the best move's interval lies entirely above the interval of every other move
+    pub separated: bool,
+}
+
+// Wilson score interval of `wins` out of `sims` for the normal quantile `z`,
+// unlike mean +- z * standard error it stays inside 0 to 1 and holds up for
+// few visits and win rates near 0 or 1
+pub fn wilson_interval(wins: f32, sims: i32, z: f32) -> (f32, f32) {
+    if sims <= 0 {
+        return (0.0, 1.0);
+    }
+    let n = sims as f32;
+    let p = (wins / n).clamp(0.0, 1.0);
+    let z2 = z * z;
+    let denominator = 1.0 + z2 / n;
+    let centre = (p + z2 / (2.0 * n)) / denominator;
+    let half = z / denominator * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((centre - half).max(0.0), (centre + half).min(1.0))
+}
+
+impl Analysis {
+    // `best` is the move the search would play, one of `children`
+    pub fn new(children: &[RootChild], best: Action, z: f32) -> Self {
+        let mut moves: Vec<MoveAnalysis> = children
+            .iter()
+            .map(|child| {
+                let (lower, upper) = wilson_interval(child.wins, child.sims, z);
+                MoveAnalysis {
+                    action: child.action,
+                    visits: child.sims,
+                    win_rate: if child.sims > 0 {
+                        child.wins / child.sims as f32
+                    } else {
+                        0.5
+                    },
+                    lower,
+                    upper,
+                }
+            })
+            .collect();
+        moves.sort_by_key(|analysis| std::cmp::Reverse(analysis.visits));
+
+        let separated = moves
+            .iter()
+            .find(|analysis| analysis.action == best)
+            .is_some_and(|best_move| {
+                moves
+                    .iter()
+                    .filter(|analysis| analysis.action != best)
+                    .all(|other| best_move.lower > other.upper)
+            });
+        Self {
+            moves,
+            best,
+            separated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!((actual.0 - expected.0).abs() < 1e-3, "{:?}", actual);
+        assert!((actual.1 - expected.1).abs() < 1e-3, "{:?}", actual);
+    }
+
+    #[test]
+    fn wilson_interval_matches_known_values() {
+        assert_close(wilson_interval(8.0, 10, 1.96), (0.4902, 0.9433));
+        assert_close(wilson_interval(0.0, 10, 1.96), (0.0, 0.2775));
+        assert_close(wilson_interval(50.0, 100, 1.96), (0.4038, 0.5962));
+        assert_close(wilson_interval(0.0, 0, 1.96), (0.0, 1.0));
+    }
+}
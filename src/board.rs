@@ -1,21 +1,12 @@
-use std::{
-    fmt::{self, write},
-    io::Empty,
-    iter::Cloned,
-    ops::ControlFlow,
-    slice::SliceIndex,
-};
-
-use bit_vec::{BitVec, Blocks};
-use rand::{prelude::SliceRandom, seq::index, Rng};
-static BITS_PS: usize = 3;
-#[derive(Clone)]
+use std::fmt;
+
+use rand::Rng;
+#[derive(Clone, PartialEq, Eq)]
 pub struct Board {
     internal_state: [Piece; 64],
     current_turn: Color,
-    last_turn: Option<Color>,
 }
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Piece {
     Filled(Color, bool),
     Empty,
@@ -26,19 +17,52 @@ pub enum Color {
     Black,
     Red,
 }
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 
 pub enum Action {
     Move(usize, usize, usize, usize),
     Capture(usize, usize, usize, usize, usize, usize),
 }
 
-const STATIC_SIZE: usize = 25;
+// enough for every legal move: 12 pieces with 4 directions each
+const STATIC_SIZE: usize = 48;
+
+// Zobrist keys: one per (square, piece kind) plus one for the side to move.
+// Generated at compile time so hashes are stable between runs and can be
+// written to disk (e.g. by the opening book).
+const ZOBRIST_PIECES: [[u64; 4]; 64] = zobrist_pieces();
+const ZOBRIST_RED_TO_MOVE: u64 = splitmix64(0x5eed_0fc0_ffee).1;
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn zobrist_pieces() -> [[u64; 4]; 64] {
+    let mut keys = [[0; 4]; 64];
+    let mut state = 0x0123_4567_89ab_cdef;
+    let mut square = 0;
+    while square < 64 {
+        let mut kind = 0;
+        while kind < 4 {
+            let (next, key) = splitmix64(state);
+            state = next;
+            keys[square][kind] = key;
+            kind += 1;
+        }
+        square += 1;
+    }
+    keys
+}
 
 const KING_MOVES: [(i32, i32); 4] = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
 const BLACK_MOVES: [(i32, i32); 2] = [(1, 1), (-1, 1)];
 const RED_MOVES: [(i32, i32); 2] = [(1, -1), (-1, -1)];
 
+#[derive(Clone, Copy)]
 pub struct StaticList<T> {
     mem: [Option<T>; STATIC_SIZE],
     len: usize,
@@ -80,7 +104,7 @@ impl MoveMemHandler {
         if self.contains_capture() {
             return self.captures.get(index);
         }
-        return self.moves.get(index);
+        self.moves.get(index)
     }
 
     pub fn clear(&mut self) {
@@ -89,26 +113,34 @@ impl MoveMemHandler {
     }
 
     pub fn contains_capture(&self) -> bool {
-        return self.captures.len() > 0;
+        self.captures.len() > 0
     }
 
     pub fn has_actions(&self) -> bool {
-        return self.captures.len() > 0 || self.moves.len() > 0;
+        self.captures.len() > 0 || self.moves.len() > 0
     }
 
     pub fn len(&self) -> usize {
         if self.contains_capture() {
             return self.captures.len();
         }
-        return self.moves.len();
+        self.moves.len()
     }
 
-    pub fn get_random_move(&self) -> Action {
+    // copies the current moves out so the handler can be reused while they are iterated
+    pub fn actions(&self) -> StaticList<Action> {
         if self.contains_capture() {
-            let index = rand::thread_rng().gen_range(0..self.captures.len());
+            return self.captures;
+        }
+        self.moves
+    }
+
+    pub fn get_random_move<R: Rng>(&self, rng: &mut R) -> Action {
+        if self.contains_capture() {
+            let index = rng.gen_range(0..self.captures.len());
             return self.captures.get(index);
         } else if self.moves.len() > 0 {
-            let index = rand::thread_rng().gen_range(0..self.moves.len());
+            let index = rng.gen_range(0..self.moves.len());
             return self.moves.get(index);
         }
         panic!("Called get random moves with no moves available")
@@ -136,7 +168,7 @@ where
     }
 
     pub fn len(&self) -> usize {
-        return self.len;
+        self.len
     }
 
     pub fn clear(&mut self) {
@@ -144,7 +176,7 @@ where
     }
 
     pub fn get(&self, index: usize) -> T {
-        return self.mem[index].unwrap();
+        self.mem[index].unwrap()
     }
 }
 
@@ -171,7 +203,74 @@ impl fmt::Display for Piece {
                     }
                 )
             }
-            Piece::Empty => write!(f, "{}", "_"),
+            Piece::Empty => write!(f, "_"),
+        }
+    }
+}
+
+// squares are written as x,y; moves use '-' and captures use 'x' e.g. 1,2-2,3 or 1,2x3,4
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Action::Move(x, y, nx, ny) => write!(f, "{},{}-{},{}", x, y, nx, ny),
+            Action::Capture(x, y, nx, ny, _, _) => write!(f, "{},{}x{},{}", x, y, nx, ny),
+        }
+    }
+}
+
+impl Action {
+    pub fn parse(text: &str) -> Option<Action> {
+        let (from, to, capture) = match text.trim().split_once('-') {
+            Some((from, to)) => (from, to, false),
+            None => {
+                let (from, to) = text.trim().split_once('x')?;
+                (from, to, true)
+            }
+        };
+        let square = |s: &str| -> Option<(usize, usize)> {
+            let (x, y) = s.split_once(',')?;
+            Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+        };
+        let (x, y) = square(from)?;
+        let (nx, ny) = square(to)?;
+        if capture {
+            Some(Action::Capture(x, y, nx, ny, (x + nx) / 2, (y + ny) / 2))
+        } else {
+            Some(Action::Move(x, y, nx, ny))
+        }
+    }
+
+    pub fn pack(self) -> PackedAction {
+        let (x, y, nx, ny, capture) = match self {
+            Action::Move(x, y, nx, ny) => (x, y, nx, ny, 0),
+            Action::Capture(x, y, nx, ny, _, _) => (x, y, nx, ny, 1),
+        };
+        PackedAction((capture << 12 | x << 9 | y << 6 | nx << 3 | ny) as u16)
+    }
+}
+
+// An action in two bytes instead of the 56 of `Action`, for storing many of them.
+// Bits: capture flag, then x, y, nx and ny with 3 bits each. The captured
+// square is not stored, a capture always jumps over the middle square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PackedAction(u16);
+
+impl PackedAction {
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn unpack(self) -> Action {
+        let bits = self.0 as usize;
+        let (x, y, nx, ny) = (bits >> 9 & 7, bits >> 6 & 7, bits >> 3 & 7, bits & 7);
+        if bits >> 12 & 1 == 1 {
+            Action::Capture(x, y, nx, ny, (x + nx) / 2, (y + ny) / 2)
+        } else {
+            Action::Move(x, y, nx, ny)
         }
     }
 }
@@ -183,6 +282,16 @@ enum MoveType {
 }
 
 impl Piece {
+    fn zobrist_kind(&self) -> Option<usize> {
+        match *self {
+            Piece::Filled(Color::Black, false) => Some(0),
+            Piece::Filled(Color::Black, true) => Some(1),
+            Piece::Filled(Color::Red, false) => Some(2),
+            Piece::Filled(Color::Red, true) => Some(3),
+            Piece::Empty => None,
+        }
+    }
+
     fn get_dirs(&self) -> MoveType {
         match self {
             Piece::Filled(color, king) => match king {
@@ -196,48 +305,66 @@ impl Piece {
         }
     }
 
+    // whether the piece may step in direction xd, yd
+    fn moves_in(&self, xd: i32, yd: i32) -> bool {
+        match self.get_dirs() {
+            MoveType::King(dirs) => dirs.contains(&(xd, yd)),
+            MoveType::Normal(dirs) => dirs.contains(&(xd, yd)),
+            MoveType::Empty => false,
+        }
+    }
+
     pub fn king_y_con(&self) -> usize {
-        match self {
-            &Piece::Filled(color, _) => match color {
+        match *self {
+            Piece::Filled(color, _) => match color {
                 Color::Black => 7,
                 Color::Red => 0,
             },
-            &Piece::Empty => 4,
+            Piece::Empty => 4,
         }
     }
 }
 
 impl Color {
     pub fn opposite(&self) -> Color {
-        match self {
-            &Color::Black => Color::Red,
-            &Color::Red => Color::Black,
+        match *self {
+            Color::Black => Color::Red,
+            Color::Red => Color::Black,
         }
     }
 }
 
 impl Board {
     pub fn get_current_color(&self) -> Color {
-        return self.current_turn;
+        self.current_turn
     }
 
-    pub fn get_last_turn(&self) -> Option<Color> {
-        return self.last_turn;
+    // zobrist hash of the piece placement and side to move,
+    // equal positions reached by different move orders hash the same
+    pub fn hash(&self) -> u64 {
+        let mut hash = match self.current_turn {
+            Color::Black => 0,
+            Color::Red => ZOBRIST_RED_TO_MOVE,
+        };
+        for (square, piece) in self.internal_state.iter().enumerate() {
+            if let Some(kind) = piece.zobrist_kind() {
+                hash ^= ZOBRIST_PIECES[square][kind];
+            }
+        }
+        hash
     }
 
     pub fn new(starting_color: Color) -> Self {
         Self {
             internal_state: [Piece::Empty; 64],
             current_turn: starting_color,
-            last_turn: None,
         }
     }
 
     pub fn clone(&self) -> Self {
         Self {
-            internal_state: self.internal_state.clone(),
-            current_turn: self.current_turn.clone(),
-            last_turn: self.last_turn.clone(),
+            internal_state: self.internal_state,
+            current_turn: self.current_turn,
         }
     }
 
@@ -256,10 +383,10 @@ impl Board {
                         match cap_piece {
                             Piece::Empty => {
                                 //can move into close spot
-                                return Some(Action::Move(x, y, cx, cy));
+                                Some(Action::Move(x, y, cx, cy))
                             }
                             Piece::Filled(cap_color, _) => {
-                                return if cap_color != self_color {
+                                if cap_color != self_color {
                                     match move_to {
                                         Some(move_to) => match move_to {
                                             Piece::Filled(_, _) => None,
@@ -323,18 +450,17 @@ impl Board {
     pub fn execute_action(&mut self, action: Action) {
         match action {
             Action::Move(x, y, nx, ny) => {
-                let piece = self.get_piece(x, y).unwrap().clone();
+                let piece = self.get_piece(x, y).unwrap();
                 // println!("moved a {} at {}, {} to {}, {}", piece, x, y, nx, ny);
                 self.set_piece(x, y, Piece::Empty);
                 self.set_piece(nx, ny, piece);
                 if ny == piece.king_y_con() {
                     self.king_piece(nx, ny)
                 }
-                self.last_turn = Some(self.current_turn);
                 self.current_turn = self.current_turn.opposite();
             }
             Action::Capture(x, y, nx, ny, cx, cy) => {
-                let piece = self.get_piece(x, y).unwrap().clone();
+                let piece = self.get_piece(x, y).unwrap();
                 // println!("captured with a {}", piece);
                 self.set_piece(x, y, Piece::Empty);
                 self.set_piece(cx, cy, Piece::Empty);
@@ -342,41 +468,75 @@ impl Board {
                 if ny == piece.king_y_con() {
                     self.king_piece(nx, ny)
                 }
-
-                if !self.piece_has_capture(nx, ny) {
-                    self.last_turn = Some(self.current_turn);
-                }
             }
         }
     }
 
-    fn piece_has_capture(&self, x: usize, y: usize) -> bool {
-        let piece = self.get_piece(x, y);
-        if let Some(p) = piece {
-            let dirs = p.get_dirs();
-            match dirs {
-                MoveType::King(king) => {
-                    for (xd, yd) in king {
-                        if let Some(action) = self.get_action(x, y, xd, yd, p) {
-                            if let Action::Capture(..) = action {
-                                return true;
-                            }
-                        }
-                    }
+    // whether `side` has any move whoever is to move, stopping at the first one found,
+    // false once it has no pieces
+    pub fn can_move(&self, side: Color) -> bool {
+        (0..64).any(|square| {
+            let (x, y) = (square % 8, square / 8);
+            match self.internal_state[square] {
+                piece @ Piece::Filled(color, _) if color == side => {
+                    KING_MOVES.iter().any(|&(xd, yd)| {
+                        piece.moves_in(xd, yd) && self.get_action(x, y, xd, yd, piece).is_some()
+                    })
                 }
-                MoveType::Normal(norm) => {
-                    for (xd, yd) in norm {
-                        if let Some(action) = self.get_action(x, y, xd, yd, p) {
-                            if let Action::Capture(..) = action {
-                                return true;
-                            }
-                        }
-                    }
+                _ => false,
+            }
+        })
+    }
+
+    pub fn has_pieces(&self, side: Color) -> bool {
+        self.internal_state
+            .iter()
+            .any(|piece| matches!(piece, Piece::Filled(color, _) if *color == side))
+    }
+
+    // whether the other side could jump the piece on x, y right away
+    pub fn is_attacked(&self, x: usize, y: usize) -> bool {
+        let color = match self.get_piece(x, y) {
+            Some(Piece::Filled(color, _)) => color,
+            _ => return false,
+        };
+        KING_MOVES.iter().any(|&(xd, yd)| {
+            let ax = (x as i32 - xd) as usize;
+            let ay = (y as i32 - yd) as usize;
+            match self.get_piece(ax, ay) {
+                Some(attacker @ Piece::Filled(attacker_color, _)) => {
+                    attacker_color != color
+                        && attacker.moves_in(xd, yd)
+                        && matches!(
+                            self.get_action(ax, ay, xd, yd, attacker),
+                            Some(Action::Capture(..))
+                        )
                 }
-                MoveType::Empty => return false,
+                _ => false,
+            }
+        })
+    }
+
+    // the most pieces the piece on x, y can take in one turn by jumping on
+    pub fn longest_capture_from(&self, x: usize, y: usize) -> usize {
+        let piece = match self.get_piece(x, y) {
+            Some(piece @ Piece::Filled(..)) => piece,
+            _ => return 0,
+        };
+        let mut longest = 0;
+        for (xd, yd) in KING_MOVES {
+            if !piece.moves_in(xd, yd) {
+                continue;
+            }
+            if let Some(capture @ Action::Capture(_, _, nx, ny, _, _)) =
+                self.get_action(x, y, xd, yd, piece)
+            {
+                let mut after = self.clone();
+                after.execute_action(capture);
+                longest = longest.max(1 + after.longest_capture_from(nx, ny));
             }
         }
-        return false;
+        longest
     }
 
     fn king_piece(&mut self, x: usize, y: usize) {
@@ -412,19 +572,6 @@ impl Board {
         }
     }
 
-    // makes a move leaves the move_mem in an empty state
-    pub fn make_random_move(&mut self, move_mem: &mut MoveMemHandler) -> Option<Color> {
-        move_mem.clear();
-        self.get_all_actions(move_mem);
-        if !move_mem.has_actions() {
-            return Some(self.current_turn.opposite());
-        }
-        let act = move_mem.get_random_move();
-        self.execute_action(act);
-        move_mem.clear();
-        None
-    }
-
     pub fn reset(&mut self) {
         for y in 0..3 {
             for x in 0..8 {
@@ -448,7 +595,7 @@ impl Board {
             return None;
         }
         let ptr = y * 8 + x;
-        return Some(self.internal_state[ptr]);
+        Some(self.internal_state[ptr])
     }
 
     pub fn set_piece(&mut self, x: usize, y: usize, piece: Piece) {
@@ -456,13 +603,32 @@ impl Board {
         self.internal_state[ptr] = piece;
     }
 
+    // one line per board for logs and exports: rows y = 0..7 split by '/',
+    // then the side to move, e.g. "b_b_b_b_/.../_r_r_r_r b"
+    pub fn snapshot(&self) -> String {
+        let mut text = String::with_capacity(74);
+        for y in 0..8 {
+            if y > 0 {
+                text.push('/');
+            }
+            for x in 0..8 {
+                text.push_str(&self.get_piece(x, y).unwrap().to_string());
+            }
+        }
+        text.push_str(match self.current_turn {
+            Color::Black => " b",
+            Color::Red => " r",
+        });
+        text
+    }
+
     pub fn print_board(&self) {
-        print!("{}", "-----------------\n");
+        println!("-----------------");
         for y in 0..8 {
             for x in 0..8 {
                 print!("|{}", self.get_piece(x, y).unwrap())
             }
-            print!("{}", "|\n-----------------\n")
+            print!("|\n-----------------\n")
         }
     }
 }
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use rand::Rng;
+
+use crate::board::{Action, Board, MoveMemHandler};
+use crate::game::GameRecord;
+
+// how many plies from the start of a game are stored in the book
+const DEFAULT_MAX_PLY: usize = 16;
+// lines scoring below this (once they have been played enough) are no longer
+// picked, the engine searches instead and may find a better book move
+const DEFAULT_MIN_SCORE: f32 = 0.4;
+const MIN_GAMES_TO_JUDGE: u32 = 4;
+// probe weight of a line without games, see `BookEntry::score`
+const NEW_LINE_WEIGHT: f32 = 0.25;
+
+#[derive(Clone, Copy)]
+pub struct BookEntry {
+    pub action: Action,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// Opening book that learns from the engine's own games.
+// Every position along a played line keeps a record of how the move played
+// from it scored for the side that played it, so lines that keep losing are
+// picked less and less often and eventually dropped in favour of a search.
+pub struct OpeningBook {
+    positions: HashMap<u64, Vec<BookEntry>>,
+    max_ply: usize,
+    min_score: f32,
+}
+
+impl BookEntry {
+    fn new(action: Action) -> Self {
+        Self {
+            action,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // expected score with one virtual win and one virtual loss,
+    // keeps a single lucky or unlucky game from deciding the line
+    pub fn score(&self) -> f32 {
+        (self.wins as f32 + 0.5 * self.draws as f32 + 1.0) / (self.games() as f32 + 2.0)
+    }
+
+    fn is_losing(&self, min_score: f32) -> bool {
+        self.games() >= MIN_GAMES_TO_JUDGE && self.score() < min_score
+    }
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        Self {
+            positions: HashMap::new(),
+            max_ply: DEFAULT_MAX_PLY,
+            min_score: DEFAULT_MIN_SCORE,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn entries(&self, board: &Board) -> &[BookEntry] {
+        self.positions
+            .get(&board.hash())
+            .map(|entries| entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // Picks a book move for the board, weighted towards lines that have scored well.
+    // Searching instead is one more candidate, weighted like a new line times the
+    // share of legal moves the book has not tried yet, so a position keeps getting
+    // new lines until every move has been played. Returns None to search, also when
+    // the position is unknown or every known line keeps losing.
+    pub fn probe<R: Rng>(
+        &self,
+        board: &Board,
+        move_mem: &mut MoveMemHandler,
+        rng: &mut R,
+    ) -> Option<Action> {
+        board.get_all_actions(move_mem);
+        let legal = |action: Action| (0..move_mem.len()).any(|i| move_mem.get(i) == action);
+
+        let entries = self.entries(board);
+        let candidates: Vec<(Action, f32)> = entries
+            .iter()
+            .filter(|entry| !entry.is_losing(self.min_score) && legal(entry.action))
+            .map(|entry| (entry.action, entry.score() * entry.score()))
+            .collect();
+        let tried = entries.iter().filter(|entry| legal(entry.action)).count();
+        let untried = move_mem.len().saturating_sub(tried);
+        let search = NEW_LINE_WEIGHT * untried as f32 / move_mem.len().max(1) as f32;
+
+        let total: f32 = candidates.iter().map(|(_, weight)| weight).sum::<f32>() + search;
+        if candidates.is_empty() || total <= 0.0 {
+            return None;
+        }
+        let mut pick = rng.gen_range(0.0..total);
+        for (action, weight) in &candidates {
+            if pick < *weight {
+                return Some(*action);
+            }
+            pick -= weight;
+        }
+        None
+    }
+
+    // updates every book move along the game's opening with the final result
+    pub fn learn(&mut self, game: &GameRecord) {
+        for played in game.moves.iter().take(self.max_ply) {
+            let entries = self.positions.entry(played.hash).or_default();
+            let index = match entries.iter().position(|e| e.action == played.action) {
+                Some(index) => index,
+                None => {
+                    entries.push(BookEntry::new(played.action));
+                    entries.len() - 1
+                }
+            };
+            let entry = &mut entries[index];
+            match game.winner {
+                Some(winner) if winner == played.color => entry.wins += 1,
+                Some(_) => entry.losses += 1,
+                None => entry.draws += 1,
+            }
+        }
+    }
+
+    // text format, one move per line: <hash> <action> <wins> <draws> <losses>
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        for (hash, entries) in &self.positions {
+            for entry in entries {
+                writeln!(
+                    file,
+                    "{:016x} {} {} {} {}",
+                    hash, entry.action, entry.wins, entry.draws, entry.losses
+                )?;
+            }
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut book = Self::new();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let parsed = Self::parse_line(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad book entry on line {}: {}", number + 1, line),
+                )
+            })?;
+            let (hash, entry) = parsed;
+            book.positions.entry(hash).or_default().push(entry);
+        }
+        Ok(book)
+    }
+
+    fn parse_line(line: &str) -> Option<(u64, BookEntry)> {
+        let mut fields = line.split_whitespace();
+        let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let action = Action::parse(fields.next()?)?;
+        let mut count = || fields.next()?.parse::<u32>().ok();
+        let entry = BookEntry {
+            action,
+            wins: count()?,
+            draws: count()?,
+            losses: count()?,
+        };
+        Some((hash, entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Color;
+    use crate::game::{self, PlayedMove};
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    // two plies from the start, won by Black
+    fn short_game() -> GameRecord {
+        let mut board = game::starting_board();
+        let mut move_mem = MoveMemHandler::new();
+        let mut moves = Vec::new();
+        for _ in 0..2 {
+            board.get_all_actions(&mut move_mem);
+            let action = move_mem.get(0);
+            moves.push(PlayedMove {
+                hash: board.hash(),
+                color: board.get_current_color(),
+                action,
+            });
+            board.execute_action(action);
+        }
+        GameRecord {
+            moves,
+            winner: Some(Color::Black),
+        }
+    }
+
+    #[test]
+    fn a_learned_line_survives_saving_and_is_probed() {
+        let record = short_game();
+        let mut book = OpeningBook::new();
+        book.learn(&record);
+        let path = std::env::temp_dir().join(format!("book-test-{}.txt", std::process::id()));
+        book.save(&path).unwrap();
+        let book = OpeningBook::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let start = game::starting_board();
+        assert_eq!(book.len(), 2);
+        let entries = book.entries(&start);
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].wins, entries[0].games()), (1, 1));
+
+        // the book plays its line or searches to try another one
+        let mut rng = SmallRng::seed_from_u64(42);
+        let mut move_mem = MoveMemHandler::new();
+        let probes: Vec<Option<Action>> = (0..100)
+            .map(|_| book.probe(&start, &mut move_mem, &mut rng))
+            .collect();
+        assert!(probes.contains(&Some(record.moves[0].action)));
+        assert!(probes.contains(&None));
+        assert!(probes
+            .iter()
+            .flatten()
+            .all(|&action| action == record.moves[0].action));
+    }
+}
@@ -0,0 +1,255 @@
+use std::time::{Duration, Instant};
+
+use crate::board::Color;
+
+// without a move counter we assume the game lasts about this many more moves
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// never plan to spend more than this fraction of the remaining time on one move
+const MAX_TIME_FRACTION: f32 = 0.4;
+// the soft limit may stretch by this much per unit of best move instability
+const INSTABILITY_EXTENSION: f32 = 0.5;
+const MAX_INSTABILITY: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug)]
+pub enum TimeControl {
+    SuddenDeath { base: Duration },
+    Fischer { base: Duration, increment: Duration },
+    // `moves` moves must be made in `time`, after which the clock is topped up again
+    MovesInTime { moves: u32, time: Duration },
+}
+
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    moves_made: [u32; 2],
+    started: Option<Instant>,
+}
+
+// how long one move may take: the search aims to stop at `soft`
+// and must stop at `hard`
+#[derive(Clone, Copy, Debug)]
+pub struct MoveTime {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+pub struct TimeManager {
+    // time lost per move outside the search (printing, the GUI, the network...)
+    pub move_overhead: Duration,
+}
+
+fn index(color: Color) -> usize {
+    match color {
+        Color::Black => 0,
+        Color::Red => 1,
+    }
+}
+
+impl TimeControl {
+    // "300" is sudden death in seconds, "60+1" adds an increment,
+    // "40/300" is 40 moves in 300 seconds
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let seconds = |s: &str| {
+            let seconds = s.trim().parse::<f32>().ok()?;
+            (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f32(seconds))
+        };
+        if let Some((base, increment)) = text.split_once('+') {
+            return Some(TimeControl::Fischer {
+                base: seconds(base)?,
+                increment: seconds(increment)?,
+            });
+        }
+        if let Some((moves, time)) = text.split_once('/') {
+            return Some(TimeControl::MovesInTime {
+                moves: moves.trim().parse().ok().filter(|&moves| moves > 0)?,
+                time: seconds(time)?,
+            });
+        }
+        Some(TimeControl::SuddenDeath {
+            base: seconds(text)?,
+        })
+    }
+
+    fn initial(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { base } => base,
+            TimeControl::Fischer { base, .. } => base,
+            TimeControl::MovesInTime { time, .. } => time,
+        }
+    }
+
+    fn increment(&self) -> Duration {
+        match *self {
+            TimeControl::Fischer { increment, .. } => increment,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            remaining: [control.initial(); 2],
+            moves_made: [0; 2],
+            started: None,
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        self.remaining[index(color)]
+    }
+
+    // moves left before the next time control, None when the rest of the game must be played
+    pub fn moves_to_go(&self, color: Color) -> Option<u32> {
+        match self.control {
+            TimeControl::MovesInTime { moves, .. } => {
+                Some(moves - self.moves_made[index(color)] % moves)
+            }
+            _ => None,
+        }
+    }
+
+    // starts the clock of the side about to move
+    pub fn start(&mut self) {
+        self.started = Some(Instant::now());
+    }
+
+    // stops the clock after `color` moved, returns false if they ran out of time
+    pub fn stop(&mut self, color: Color) -> bool {
+        let elapsed = self.started.take().map(|s| s.elapsed()).unwrap_or_default();
+        self.charge(color, elapsed)
+    }
+
+    // takes `elapsed` off the mover's clock and applies the increment or the next
+    // time control, returns false if the move took longer than the time left
+    pub fn charge(&mut self, color: Color, elapsed: Duration) -> bool {
+        let side = index(color);
+        if elapsed > self.remaining[side] {
+            self.remaining[side] = Duration::ZERO;
+            return false;
+        }
+        self.remaining[side] -= elapsed;
+        self.remaining[side] += self.control.increment();
+        self.moves_made[side] += 1;
+        if let TimeControl::MovesInTime { moves, time } = self.control {
+            if self.moves_made[side] % moves == 0 {
+                self.remaining[side] += time;
+            }
+        }
+        true
+    }
+}
+
+impl MoveTime {
+    pub fn fixed(time: Duration) -> Self {
+        Self {
+            soft: time,
+            hard: time,
+        }
+    }
+
+    // the search stops at the soft limit unless the best move keeps changing,
+    // an unstable search is allowed to run on towards the hard limit
+    pub fn should_stop(&self, elapsed: Duration, instability: f32) -> bool {
+        let extension = 1.0 + INSTABILITY_EXTENSION * instability.min(MAX_INSTABILITY);
+        elapsed >= self.hard || elapsed >= self.soft.mul_f32(extension)
+    }
+}
+
+impl TimeManager {
+    pub fn new() -> Self {
+        Self {
+            move_overhead: Duration::from_millis(10),
+        }
+    }
+
+    // splits the remaining time over the moves still to play, spending more
+    // when there are many legal moves and next to nothing on forced moves
+    pub fn allocate(&self, clock: &Clock, color: Color, legal_moves: usize) -> MoveTime {
+        let remaining = clock.remaining(color).saturating_sub(self.move_overhead);
+        if legal_moves <= 1 {
+            return MoveTime::fixed(Duration::ZERO);
+        }
+
+        let moves_to_go = clock.moves_to_go(color).unwrap_or(DEFAULT_MOVES_TO_GO);
+        let increment = clock.control().increment();
+        let base = remaining / moves_to_go + increment.mul_f32(0.8);
+        let complexity = (0.6 + 0.08 * legal_moves as f32).clamp(0.7, 1.5);
+
+        let ceiling = remaining.mul_f32(MAX_TIME_FRACTION);
+        let soft = base.mul_f32(complexity).min(ceiling);
+        let hard = (soft * 3).min(ceiling).max(soft);
+        MoveTime { soft, hard }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: f32) -> Duration {
+        Duration::from_secs_f32(seconds)
+    }
+
+    #[test]
+    fn charge_adds_the_increment() {
+        let mut clock = Clock::new(TimeControl::parse("10+2").unwrap());
+        assert!(clock.charge(Color::Black, secs(3.0)));
+        assert_eq!(clock.remaining(Color::Black), secs(9.0));
+        assert_eq!(clock.remaining(Color::Red), secs(10.0));
+        assert!(!clock.charge(Color::Black, secs(20.0)));
+        assert_eq!(clock.remaining(Color::Black), Duration::ZERO);
+    }
+
+    #[test]
+    fn charge_tops_up_at_the_nth_move() {
+        let mut clock = Clock::new(TimeControl::parse("2/10").unwrap());
+        assert_eq!(clock.moves_to_go(Color::Black), Some(2));
+        assert!(clock.charge(Color::Black, secs(3.0)));
+        assert_eq!(clock.remaining(Color::Black), secs(7.0));
+        assert_eq!(clock.moves_to_go(Color::Black), Some(1));
+        assert!(clock.charge(Color::Black, secs(3.0)));
+        assert_eq!(clock.remaining(Color::Black), secs(14.0));
+        assert_eq!(clock.moves_to_go(Color::Black), Some(2));
+        assert_eq!(clock.moves_to_go(Color::Red), Some(2));
+    }
+
+    #[test]
+    fn only_a_move_counter_has_moves_to_go() {
+        assert_eq!(
+            Clock::new(TimeControl::parse("300").unwrap()).moves_to_go(Color::Red),
+            None
+        );
+        assert_eq!(
+            Clock::new(TimeControl::parse("60+1").unwrap()).moves_to_go(Color::Red),
+            None
+        );
+        assert!(TimeControl::parse("0/10").is_none());
+    }
+
+    #[test]
+    fn allocate_splits_the_remaining_time() {
+        let manager = TimeManager::new();
+        let clock = Clock::new(TimeControl::parse("60").unwrap());
+        assert_eq!(
+            manager.allocate(&clock, Color::Black, 1).hard,
+            Duration::ZERO
+        );
+
+        // 59.99 s over 30 moves, times 1.4 for 10 legal moves
+        let time = manager.allocate(&clock, Color::Black, 10);
+        assert!((time.soft.as_secs_f32() - 2.8).abs() < 0.01, "{:?}", time);
+        assert_eq!(time.hard, time.soft * 3);
+
+        // 2 moves to go would want 7 s, capped at 40% of the time left
+        let clock = Clock::new(TimeControl::parse("2/10").unwrap());
+        let time = manager.allocate(&clock, Color::Black, 10);
+        assert!((time.soft.as_secs_f32() - 3.996).abs() < 0.01, "{:?}", time);
+        assert_eq!(time.hard, time.soft);
+    }
+}
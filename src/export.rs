@@ -0,0 +1,95 @@
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use crate::board::{Action, Color};
+
+// Writes what a search explored so bad moves can be looked at by hand, as
+// Graphviz DOT (`dot -Tsvg tree.dot -o tree.svg`) or as JSON for scripts.
+// Large trees are pruned while they are collected, see `ExportOptions`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExportOptions {
+    // only the most simulated children of every node
+    pub top_k: Option<usize>,
+    pub min_visits: i32,
+    // the root is depth 0
+    pub max_depth: Option<usize>,
+}
+
+// one node of an exported tree, `parent` indexes the same list
+#[derive(Clone, Debug)]
+pub struct ExportedNode {
+    pub parent: Option<usize>,
+    pub action: Option<Action>,
+    pub visits: i32,
+    pub wins: f32,
+    // the score selection gave the node last, None for the root
+    pub uct: Option<f32>,
+    pub depth: usize,
+    pub proven: Option<Color>,
+    pub board: String,
+}
+
+pub fn to_dot(nodes: &[ExportedNode]) -> String {
+    let mut dot = String::from("digraph tree {\n    node [shape=box, fontname=monospace];\n");
+    for (id, node) in nodes.iter().enumerate() {
+        let action = node
+            .action
+            .map_or_else(|| "root".to_string(), |a| a.to_string());
+        let mut label = format!(
+            "{}\\n{:.1}/{} ({:.0}%)",
+            action,
+            node.wins,
+            node.visits,
+            100.0 * node.wins / node.visits.max(1) as f32
+        );
+        if let Some(uct) = node.uct {
+            let _ = write!(label, "\\nuct {:.3}", uct);
+        }
+        if let Some(winner) = node.proven {
+            let _ = write!(label, "\\n{:?} wins", winner);
+        }
+        let _ = writeln!(
+            dot,
+            "    n{} [label=\"{}\", tooltip=\"{}\"];",
+            id, label, node.board
+        );
+        if let Some(parent) = node.parent {
+            let _ = writeln!(dot, "    n{} -> n{};", parent, id);
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+pub fn to_json(nodes: &[ExportedNode]) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    let mut json = String::from("{\"nodes\": [\n");
+    for (id, node) in nodes.iter().enumerate() {
+        let _ = write!(
+            json,
+            "  {{\"id\": {}, \"parent\": {}, \"action\": {}, \"visits\": {}, \"wins\": {}, \
+             \"uct\": {}, \"depth\": {}, \"proven\": {}, \"board\": \"{}\"}}",
+            id,
+            optional(node.parent.map(|p| p.to_string())),
+            optional(node.action.map(|a| format!("\"{}\"", a))),
+            node.visits,
+            node.wins,
+            // JSON has no infinity, unvisited nodes score infinite under UCB1
+            optional(node.uct.filter(|u| u.is_finite()).map(|u| u.to_string())),
+            node.depth,
+            optional(node.proven.map(|c| format!("\"{:?}\"", c))),
+            node.board
+        );
+        json.push_str(if id + 1 < nodes.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]}\n");
+    json
+}
+
+// JSON for a path ending in .json, DOT otherwise
+pub fn write(nodes: &[ExportedNode], path: &Path) -> io::Result<()> {
+    let text = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => to_json(nodes),
+        _ => to_dot(nodes),
+    };
+    fs::write(path, text)
+}
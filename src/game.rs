@@ -0,0 +1,404 @@
+use std::{
+    io::{self, BufRead, Write},
+    time::Duration,
+};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::analysis::{wilson_interval, DEFAULT_Z};
+use crate::board::{Action, Board, Color, MoveMemHandler};
+use crate::book::OpeningBook;
+use crate::clock::{Clock, MoveTime, TimeControl, TimeManager};
+use crate::minimax::AlphaBeta;
+use crate::montecarlo::{SearchBudget, Tree, TreeConfig};
+use crate::ponder::Ponderer;
+use crate::strength::{LevelPlayer, StrengthLevel};
+
+// games that reach this many plies without a winner are scored as draws,
+// kings can otherwise shuffle back and forth forever
+pub const MAX_GAME_PLIES: usize = 300;
+
+pub const SELF_PLAY_TIME_CONTROL: TimeControl = TimeControl::Fischer {
+    base: Duration::from_secs(5),
+    increment: Duration::from_millis(100),
+};
+
+#[derive(Clone, Copy)]
+pub enum EngineKind {
+    MonteCarlo,
+    AlphaBeta,
+    Level(StrengthLevel),
+}
+
+#[derive(Clone, Copy)]
+pub struct PlayedMove {
+    pub hash: u64,
+    pub color: Color,
+    pub action: Action,
+}
+
+pub struct GameRecord {
+    pub moves: Vec<PlayedMove>,
+    pub winner: Option<Color>,
+}
+
+pub fn starting_board() -> Board {
+    let mut board = Board::new(Color::Black);
+    board.reset();
+    board
+}
+
+// anything that can pick a move in a game: engines, strength levels, book players
+pub trait Player {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action;
+}
+
+impl Player for AlphaBeta {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        self.get_best_move(board, time)
+    }
+}
+
+// the full strength monte carlo engine, playing from the book while it can
+pub struct BookPlayer<'a> {
+    book: &'a OpeningBook,
+    move_handler: MoveMemHandler,
+    rng: SmallRng,
+}
+
+impl<'a> BookPlayer<'a> {
+    pub fn new(book: &'a OpeningBook, seed: u64) -> Self {
+        Self {
+            book,
+            move_handler: MoveMemHandler::new(),
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for BookPlayer<'_> {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        match self
+            .book
+            .probe(board, &mut self.move_handler, &mut self.rng)
+        {
+            Some(action) => action,
+            None => Tree::with_seed(board.clone(), self.rng.gen())
+                .get_monte_carlo_move(&SearchBudget::time(time))
+                .expect("no legal moves"),
+        }
+    }
+}
+
+// the full strength monte carlo engine with a chosen configuration,
+// the tree is carried over from one move to the next
+pub struct MonteCarloPlayer {
+    config: TreeConfig,
+    rng: SmallRng,
+    tree: Option<Tree>,
+}
+
+impl MonteCarloPlayer {
+    pub fn new(config: TreeConfig, seed: u64) -> Self {
+        Self {
+            config,
+            rng: SmallRng::seed_from_u64(seed),
+            tree: None,
+        }
+    }
+}
+
+impl Player for MonteCarloPlayer {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        let mut tree = reuse_tree(self.tree.take(), board).unwrap_or_else(|| {
+            Tree::with_config(board.clone(), self.rng.gen(), self.config.clone())
+        });
+        let action = tree
+            .get_monte_carlo_move(&SearchBudget::time(time))
+            .expect("no legal moves");
+        if tree.advance(action) {
+            self.tree = Some(tree);
+        }
+        action
+    }
+}
+
+// continues from a previous search when its tree still leads to `board`,
+// re-rooting it past the opponent's move played since
+fn reuse_tree(tree: Option<Tree>, board: &Board) -> Option<Tree> {
+    let mut tree = tree?;
+    // a capture can leave the same side to move, in which case nothing was played since
+    if tree.root_board().hash() == board.hash() {
+        return Some(tree);
+    }
+    let action = tree.find_move_to(board)?;
+    tree.advance(action).then_some(tree)
+}
+
+// plays one game from the starting position, a player losing on time loses the game
+pub fn play_game(black: &mut dyn Player, red: &mut dyn Player, control: TimeControl) -> GameRecord {
+    let mut board = starting_board();
+    let mut move_handler = MoveMemHandler::new();
+    let mut moves = Vec::new();
+    let mut clock = Clock::new(control);
+    let time_manager = TimeManager::new();
+
+    while moves.len() < MAX_GAME_PLIES {
+        let color = board.get_current_color();
+        board.get_all_actions(&mut move_handler);
+        if !move_handler.has_actions() {
+            return GameRecord {
+                moves,
+                winner: Some(color.opposite()),
+            };
+        }
+
+        let time = time_manager.allocate(&clock, color, move_handler.len());
+        clock.start();
+        let action = match color {
+            Color::Black => black.choose_move(&board, time),
+            Color::Red => red.choose_move(&board, time),
+        };
+        if !clock.stop(color) {
+            return GameRecord {
+                moves,
+                winner: Some(color.opposite()),
+            };
+        }
+        moves.push(PlayedMove {
+            hash: board.hash(),
+            color,
+            action,
+        });
+        board.execute_action(action);
+    }
+
+    GameRecord {
+        moves,
+        winner: None,
+    }
+}
+
+// plays the engine against itself from the starting position,
+// taking book moves while the book has a line that is still scoring well
+pub fn play_self_play_game(book: &OpeningBook, seed: u64) -> GameRecord {
+    play_game(
+        &mut BookPlayer::new(book, seed),
+        &mut BookPlayer::new(book, seed.wrapping_add(1)),
+        SELF_PLAY_TIME_CONTROL,
+    )
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MatchResult {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchResult {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f32 {
+        (self.wins as f32 + 0.5 * self.draws as f32) / self.games().max(1) as f32
+    }
+
+    // elo difference implied by the score, clamped so a clean sweep stays finite
+    pub fn elo_difference(&self) -> f32 {
+        elo_of(self.score(), self.games())
+    }
+
+    // 95% interval of the elo difference from the Wilson interval of the score,
+    // draws counting as half a win; unlike a normal approximation it stays
+    // wide when every game has the same result
+    pub fn elo_interval(&self) -> (f32, f32) {
+        let points = self.wins as f32 + 0.5 * self.draws as f32;
+        let (lower, upper) = wilson_interval(points, self.games() as i32, DEFAULT_Z);
+        (elo_of(lower, self.games()), elo_of(upper, self.games()))
+    }
+}
+
+// the elo difference for `score`, clamped to half a game from either end
+fn elo_of(score: f32, games: u32) -> f32 {
+    let games = games.max(1) as f32;
+    let score = score.clamp(0.5 / games, 1.0 - 0.5 / games);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// plays `games` games alternating colours, the result is from `first`'s point of view,
+// `on_game` is called after every game with its number, record and the running result
+pub fn play_match(
+    first: &mut dyn Player,
+    second: &mut dyn Player,
+    games: u32,
+    control: TimeControl,
+    mut on_game: impl FnMut(u32, &GameRecord, &MatchResult),
+) -> MatchResult {
+    let mut result = MatchResult::default();
+    for game in 0..games {
+        let first_color = if game % 2 == 0 {
+            Color::Black
+        } else {
+            Color::Red
+        };
+        let record = match first_color {
+            Color::Black => play_game(first, second, control),
+            Color::Red => play_game(second, first, control),
+        };
+        match record.winner {
+            Some(winner) if winner == first_color => result.wins += 1,
+            Some(_) => result.losses += 1,
+            None => result.draws += 1,
+        }
+        on_game(game + 1, &record, &result);
+    }
+    result
+}
+
+// interactive game against the engine on stdin, moves are typed as 2,2-3,3 or 2,2x4,4
+// with `ponder` set the monte carlo engine keeps searching while the human is thinking
+pub fn play_against_human(human: Color, engine: EngineKind, control: TimeControl, ponder: bool) {
+    let mut board = starting_board();
+    let mut move_handler = MoveMemHandler::new();
+    let mut clock = Clock::new(control);
+    let time_manager = TimeManager::new();
+    let mut alpha_beta = AlphaBeta::new();
+    let mut rng = SmallRng::from_entropy();
+    let mut ponderer: Option<Ponderer> = None;
+    let mut pondered: Option<Tree> = None;
+    let mut engine_tree: Option<Tree> = None;
+
+    loop {
+        let color = board.get_current_color();
+        board.print_board();
+        println!(
+            "clock: black {:.1?} red {:.1?}",
+            clock.remaining(Color::Black),
+            clock.remaining(Color::Red)
+        );
+        board.get_all_actions(&mut move_handler);
+        if !move_handler.has_actions() {
+            println!("{:?} wins", color.opposite());
+            return;
+        }
+
+        clock.start();
+        if color == human {
+            let action = match read_human_move(&mut move_handler) {
+                Some(action) => action,
+                None => return,
+            };
+            if let Some(running) = ponderer.take() {
+                let expected = running.expected();
+                pondered = running.finish(action);
+                match pondered {
+                    Some(_) => println!("ponder hit on {}", expected),
+                    None => println!("ponder miss, expected {}", expected),
+                }
+            }
+            board.execute_action(action);
+        } else {
+            let time = time_manager.allocate(&clock, color, move_handler.len());
+            let action = match engine {
+                EngineKind::AlphaBeta => alpha_beta.get_best_move(&board, time),
+                EngineKind::Level(level) => {
+                    LevelPlayer::new(level, rng.gen()).choose_move(&board, time)
+                }
+                EngineKind::MonteCarlo => {
+                    // carry on from the pondered tree, or from the tree of the engine's
+                    // last move when there was none; after a capture the human moved
+                    // again, which reuse_tree steps past
+                    let mut tree = reuse_tree(pondered.take().or(engine_tree.take()), &board)
+                        .unwrap_or_else(|| Tree::with_seed(board.clone(), rng.gen()));
+                    let action = tree
+                        .get_monte_carlo_move(&SearchBudget::time(time))
+                        .expect("no legal moves");
+                    let mut after = board.clone();
+                    after.execute_action(action);
+                    let reply = (ponder && after.get_current_color() == human)
+                        .then(|| tree.expected_reply(action))
+                        .flatten();
+                    if tree.advance(action) {
+                        // the tree goes on to the ponderer and only comes back on a hit
+                        match reply {
+                            Some(reply) => ponderer = Some(Ponderer::start(tree, reply, rng.gen())),
+                            None => engine_tree = Some(tree),
+                        }
+                    }
+                    action
+                }
+            };
+            println!("engine plays {}", action);
+            board.execute_action(action);
+        }
+
+        if !clock.stop(color) {
+            println!("{:?} ran out of time, {:?} wins", color, color.opposite());
+            return;
+        }
+    }
+}
+
+// reads moves until a legal one is entered, None on end of input or "quit"
+fn read_human_move(move_mem: &mut MoveMemHandler) -> Option<Action> {
+    let stdin = io::stdin();
+    loop {
+        print!("your move: ");
+        io::stdout().flush().ok()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).ok()? == 0 || line.trim() == "quit" {
+            return None;
+        }
+        let legal = (0..move_mem.len()).map(|i| move_mem.get(i));
+        match Action::parse(&line) {
+            Some(action) if legal.clone().any(|a| a == action) => return Some(action),
+            _ => {
+                let options: Vec<String> = legal.map(|a| a.to_string()).collect();
+                println!("illegal move, options are: {}", options.join(" "));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_tree_is_reused_after_a_move_played_since() {
+        let board = starting_board();
+        let mut tree = Tree::with_seed(board.clone(), 1);
+        tree.search(&SearchBudget::iterations(500));
+        let action = tree.select_best_move();
+        let mut after = board.clone();
+        after.execute_action(action);
+
+        let reused = reuse_tree(Some(tree), &after).expect("the move is in the tree");
+        assert!(*reused.root_board() == after);
+        assert!(reused.node_count() > 1);
+        // nothing played since
+        assert!(reuse_tree(Some(reused), &after).is_some());
+    }
+
+    #[test]
+    fn the_elo_interval_stays_wide_when_every_game_ends_alike() {
+        let drawn = MatchResult {
+            wins: 0,
+            draws: 2,
+            losses: 0,
+        };
+        let (low, high) = drawn.elo_interval();
+        assert!(low < -100.0 && high > 100.0, "{} to {}", low, high);
+
+        let swept = MatchResult {
+            wins: 20,
+            draws: 0,
+            losses: 0,
+        };
+        let (low, high) = swept.elo_interval();
+        assert!(low > 0.0 && low < high, "{} to {}", low, high);
+        assert_eq!(high, swept.elo_difference());
+    }
+}
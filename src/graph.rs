@@ -0,0 +1,301 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    mem,
+    time::Instant,
+};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::analysis::Analysis;
+use crate::board::{Action, Board, Color, MoveMemHandler};
+use crate::clock::MoveTime;
+use crate::game::Player;
+use crate::montecarlo::{RootChild, SearchBudget, SearchProgress, TreeConfig};
+use crate::rollout::{self, Outcome};
+use crate::selection::{ChildStats, ParentStats};
+
+// Monte Carlo search over a graph of positions instead of a tree: a position
+// reached by different move orders is one node keyed by its hash, so all of
+// its simulations are shared. Selection follows UCT2 (Childs et al.,
+// "Transpositions and move groups in Monte Carlo tree search"): the value of a
+// move is the shared value of the position it leads to, while exploration
+// counts how often this particular move was taken. Results are backed up along
+// the path that was actually descended; the other parents of a position see
+// the new value through the shared statistics the next time they select.
+//
+// Only the selection and rollout policies of the config are used.
+pub struct Graph {
+    root: u64,
+    nodes: HashMap<u64, GraphNode>,
+    config: TreeConfig,
+    move_handler: MoveMemHandler,
+    rng: SmallRng,
+    edges: usize,
+    // expansions that found a child position already in the graph
+    transpositions: usize,
+}
+
+struct GraphNode {
+    board: Board,
+    sims: i32,
+    // wins are kept for Black, a position can be entered by either side
+    // because a capture does not pass the turn
+    black_wins: f32,
+    // None until expanded
+    edges: Option<Vec<Edge>>,
+}
+
+struct Edge {
+    action: Action,
+    child: u64,
+    sims: i32,
+    // for the side that played `action`
+    wins: f32,
+    prior: f32,
+}
+
+impl GraphNode {
+    fn new(board: Board) -> Self {
+        Self {
+            board,
+            sims: 0,
+            black_wins: 0.0,
+            edges: None,
+        }
+    }
+}
+
+impl Graph {
+    pub fn with_config(board: Board, seed: u64, config: TreeConfig) -> Self {
+        let root = board.hash();
+        let mut nodes = HashMap::new();
+        nodes.insert(root, GraphNode::new(board));
+        Self {
+            root,
+            nodes,
+            config,
+            move_handler: MoveMemHandler::new(),
+            rng: SmallRng::seed_from_u64(seed),
+            edges: 0,
+            transpositions: 0,
+        }
+    }
+
+    pub fn positions(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn transpositions(&self) -> usize {
+        self.transpositions
+    }
+
+    // approximate bytes held by the positions and moves, ignoring the map's spare capacity
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.len() * (mem::size_of::<u64>() + mem::size_of::<GraphNode>())
+            + self.edges * mem::size_of::<Edge>()
+    }
+
+    // same contract as Tree::search: the number of expansions,
+    // None when the root has no moves
+    pub fn search(&mut self, budget: &SearchBudget) -> Option<usize> {
+        self.expand(self.root);
+        if self.nodes[&self.root].edges.as_ref().unwrap().is_empty() {
+            return None;
+        }
+        let start = Instant::now();
+        let mut i = 0;
+        while !budget.exhausted(&SearchProgress {
+            iterations: i,
+            elapsed: start.elapsed(),
+            instability: 0.0,
+            root_sims: self.nodes[&self.root].sims,
+            memory_bytes: self.memory_usage(),
+        }) {
+            self.iterate();
+            i += 1;
+        }
+        Some(i)
+    }
+
+    fn iterate(&mut self) {
+        // (position, index of the edge taken out of it)
+        let mut path: Vec<(u64, usize)> = Vec::new();
+        let mut current = self.root;
+
+        loop {
+            let node = &self.nodes[&current];
+            let edges = match &node.edges {
+                Some(edges) => edges,
+                None => break,
+            };
+            // positions already on the path are skipped so kings moving back
+            // and forth cannot send the descent round in circles
+            let on_path = |hash: u64| hash == self.root || path.iter().any(|&(h, _)| h == hash);
+            let mover = node.board.get_current_color();
+            let parent = ParentStats::new(node.sims);
+            let best = edges
+                .iter()
+                .enumerate()
+                .filter(|(_, edge)| !on_path(edge.child))
+                .max_by(|(_, a), (_, b)| {
+                    let a = self.score(a, mover, &parent);
+                    let b = self.score(b, mover, &parent);
+                    a.total_cmp(&b)
+                });
+            match best {
+                Some((index, edge)) => {
+                    path.push((current, index));
+                    current = edge.child;
+                }
+                None => break,
+            }
+        }
+
+        self.expand(current);
+        let edges = self.nodes[&current].edges.as_ref().unwrap();
+        let fresh: Vec<usize> = (0..edges.len())
+            .filter(|&i| {
+                edges[i].child != self.root && path.iter().all(|&(h, _)| h != edges[i].child)
+            })
+            .collect();
+        if !fresh.is_empty() {
+            let index = fresh[self.rng.gen_range(0..fresh.len())];
+            let child = edges[index].child;
+            path.push((current, index));
+            current = child;
+        }
+
+        let mut board = self.nodes[&current].board.clone();
+        let outcome = rollout::play_out(
+            &mut board,
+            &*self.config.rollout,
+            self.config.rollout_cutoff,
+            &mut self.move_handler,
+            &mut self.rng,
+            None,
+        );
+        self.back_propagate(&path, current, outcome);
+    }
+
+    // UCT2: the shared mean of the child position, explored by the edge's own count
+    fn score(&self, edge: &Edge, mover: Color, parent: &ParentStats) -> f32 {
+        let child = &self.nodes[&edge.child];
+        let wins = if edge.sims == 0 || child.sims == 0 {
+            edge.wins
+        } else {
+            let black = child.black_wins / child.sims as f32;
+            let mean = match mover {
+                Color::Black => black,
+                Color::Red => 1.0 - black,
+            };
+            mean * edge.sims as f32
+        };
+        let stats = ChildStats {
+            wins,
+            sims: edge.sims,
+            prior: edge.prior,
+        };
+        self.config.selection.score(&stats, parent)
+    }
+
+    fn expand(&mut self, hash: u64) {
+        if self.nodes[&hash].edges.is_some() {
+            return;
+        }
+        let board = self.nodes[&hash].board.clone();
+        board.get_all_actions(&mut self.move_handler);
+        let actions = self.move_handler.actions();
+        let prior = 1.0 / actions.len().max(1) as f32;
+        let mut edges = Vec::with_capacity(actions.len());
+        for i in 0..actions.len() {
+            let action = actions.get(i);
+            let mut child = board.clone();
+            child.execute_action(action);
+            let child_hash = child.hash();
+            match self.nodes.entry(child_hash) {
+                Entry::Occupied(occupied) => {
+                    // the key is only the hash, a collision would merge two positions
+                    debug_assert!(occupied.get().board == child, "hash collision");
+                    self.transpositions += 1;
+                }
+                Entry::Vacant(vacant) => {
+                    vacant.insert(GraphNode::new(child));
+                }
+            }
+            edges.push(Edge {
+                action,
+                child: child_hash,
+                sims: 0,
+                wins: 0.0,
+                prior,
+            });
+        }
+        self.edges += edges.len();
+        self.nodes.get_mut(&hash).unwrap().edges = Some(edges);
+    }
+
+    fn back_propagate(&mut self, path: &[(u64, usize)], leaf: u64, outcome: Outcome) {
+        let black = outcome.value_for(Color::Black);
+        let leaf = self.nodes.get_mut(&leaf).unwrap();
+        leaf.sims += 1;
+        leaf.black_wins += black;
+        for &(hash, index) in path {
+            let node = self.nodes.get_mut(&hash).unwrap();
+            node.sims += 1;
+            node.black_wins += black;
+            let mover = node.board.get_current_color();
+            let edge = &mut node.edges.as_mut().unwrap()[index];
+            edge.sims += 1;
+            edge.wins += outcome.value_for(mover);
+        }
+    }
+
+    // the root's moves with their statistics, most simulated first
+    pub fn root_children(&self) -> Vec<RootChild> {
+        let mut children: Vec<RootChild> = self.nodes[&self.root]
+            .edges
+            .iter()
+            .flatten()
+            .map(|edge| RootChild {
+                action: edge.action,
+                sims: edge.sims,
+                wins: edge.wins,
+            })
+            .collect();
+        children.sort_by_key(|child| std::cmp::Reverse(child.sims));
+        children
+    }
+
+    pub fn select_best_move(&self) -> Option<Action> {
+        self.root_children().first().map(|child| child.action)
+    }
+
+    // the root's moves with confidence intervals from the normal quantile `z`,
+    // None when the root has no moves
+    pub fn analyze(&self, z: f32) -> Option<Analysis> {
+        let best = self.select_best_move()?;
+        Some(Analysis::new(&self.root_children(), best, z))
+    }
+}
+
+pub struct GraphPlayer {
+    config: TreeConfig,
+    rng: SmallRng,
+}
+
+impl GraphPlayer {
+    pub fn new(config: TreeConfig, seed: u64) -> Self {
+        Self {
+            config,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for GraphPlayer {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        let mut graph = Graph::with_config(board.clone(), self.rng.gen(), self.config.clone());
+        graph.search(&SearchBudget::time(time));
+        graph.select_best_move().expect("no legal moves")
+    }
+}
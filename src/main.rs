@@ -1,20 +1,619 @@
+use analysis::Analysis;
 use board::{Board, Color};
-use montecarlo::Tree;
-use std::time::Instant;
+use book::OpeningBook;
+use clock::{MoveTime, TimeControl};
+use export::ExportOptions;
+use game::{EngineKind, MonteCarloPlayer, Player};
+use graph::{Graph, GraphPlayer};
+use minimax::AlphaBeta;
+use montecarlo::{FinalMove, Hybrid, SearchBudget, Tree, TreeConfig, Widening};
+use network::Network;
+use parallel::RootParallelPlayer;
+use selection::Rave;
+use shared_tree::TreeParallelPlayer;
+use std::{
+    env,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use strength::{LevelPlayer, StrengthLevel};
+use training::{SelfPlayConfig, ShardReader};
 
+mod analysis;
 mod board;
+mod book;
+mod clock;
+mod export;
+mod game;
+mod graph;
 mod minimax;
 mod montecarlo;
+mod network;
+mod parallel;
+mod ponder;
+mod rollout;
+mod selection;
+mod shared_tree;
+mod strength;
+mod training;
 
+// usage:
+//   Minimax                          search the starting position once
+//   Minimax search <seed> [<selection>] [rollout=<rollout>] [cutoff=<plies>] [rave=<k>]
+//                  [widen=<coefficient>] [bias=<weight>] [final=<strategy>] [extend=<factor>]
+//                  [cap=<MB>] [net=<file>] [batch=<n>] [hybrid=<depth>[:<weight>]]
+//                  [analyze[=<z>]] [graph] [export=<file>] [topk=<k>] [minvisits=<n>]
+//                  [maxdepth=<d>]
+//                  [<limit>=<n>...]
+//                                    reproducible search of the start, limited to 10000
+//                                    expansions unless iterations=, time= (ms),
+//                                    playouts=, memory= (MB) or stopafter= (ms, set
+//                                    from another thread like a GUI would) are given,
+//                                    cutoff= scores playouts by evaluation after <plies>,
+//                                    rave= blends in AMAF values with equivalence <k>,
+//                                    widen= adds children progressively and bias= adds a
+//                                    decaying heuristic term to selection, final= picks
+//                                    the move by visits, value, robust or secure[:<a>],
+//                                    extend= searches up to <factor> longer while the most
+//                                    visited and best valued moves differ, cap= prunes the
+//                                    least simulated subtrees to stay under <MB>, net= scores
+//                                    leaves with a network in batches of <n> (8) and uses its
+//                                    policy as priors, with puct unless a selection is given,
+//                                    hybrid= scores new nodes with a <depth> ply alpha-beta
+//                                    search and backs the scores up as minimax values that
+//                                    selection weighs in by <weight> (0.3), analyze lists the
+//                                    root's moves with Wilson intervals (z 1.96) and whether
+//                                    the best move is separated from the rest, graph shares
+//                                    transposed positions in a graph instead of a tree,
+//                                    export= writes the tree to a .dot or .json file,
+//                                    pruned by topk=, minvisits= and maxdepth=, which a
+//                                    graph search cannot do
+//   Minimax bench [<iterations>]     playouts per second of seeded searches of the start,
+//                                    200000 expansions each unless given
+//   Minimax netinit <file> [<hidden>]
+//                                    write a network with random weights and <hidden> (64) units
+//   Minimax selfplay <games> <book>  play self-play games, learning into the book file
+//   Minimax datagen <games> <dir> [threads=<n>] [iterations=<n>] [opening=<plies>]
+//                  [sampling=<plies>] [seed=<n>] [net=<file>]
+//                                    headless self-play writing training shards to <dir>,
+//                                    one per thread (all cores), searching 400 expansions
+//                                    a move after 4 random opening moves, the first 8
+//                                    searched moves drawn by visits, replacing the shards
+//                                    of an earlier run
+//   Minimax datainfo <dir|shard>...  read shards back and summarize them
+//   Minimax play [black|red] [alphabeta|level<n>] [noponder] [<time control>]
+//                                    play against the engine on stdin, the time
+//                                    control is 300, 60+1 or 40/300 (seconds)
+//   Minimax match <player> <player> [games] [<time control>]
+//                                    engine match, players are alphabeta, level<n> or
+//                                    a selection policy for the full strength engine,
+//                                    optionally with a rollout policy and a playout
+//                                    cutoff (ucb1,softmax or ucb1,uniform,40),
+//                                    or root:<threads> / tree:<threads> for root or
+//                                    tree parallel search, graph for the transposition
+//                                    graph search, net:<file> for network evaluation or
+//                                    hybrid:<depth>[:<weight>] for the hybrid search
+//   selection policies are ucb1, ucb1:<c>, tuned, puct and puct:<c>
+//   rollout policies are uniform, softmax, softmax:<temperature>, greedy and greedy:<epsilon>
+//   followed by +decisive to always take a move that wins on the spot or +anti to also
+//   avoid moves that allow one (ucb1,uniform+anti)
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("bench") => bench(args.get(2).and_then(|n| n.parse().ok()).unwrap_or(200000)),
+        Some("netinit") => {
+            let path = Path::new(args.get(2).expect("netinit <file> [<hidden>]"));
+            let hidden = args.get(3).and_then(|n| n.parse().ok()).unwrap_or(64);
+            assert!(
+                hidden > 0,
+                "netinit <file> [<hidden>] needs at least one hidden unit"
+            );
+            Network::random(hidden, rand::random())
+                .save(path)
+                .expect("could not write the network");
+        }
+        Some("datagen") => {
+            let games = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(100);
+            let dir = Path::new(args.get(3).map(|p| p.as_str()).unwrap_or("data"));
+            data_gen(games, dir, &args[2.min(args.len())..]);
+        }
+        Some("datainfo") => data_info(&args[2..]),
+        Some("selfplay") => {
+            let games = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(10);
+            let book_path = args.get(3).map(|p| p.as_str()).unwrap_or("book.txt");
+            self_play(games, Path::new(book_path));
+        }
+        Some("play") => {
+            let human = match args.get(2).map(|c| c.as_str()) {
+                Some("red") => Color::Red,
+                _ => Color::Black,
+            };
+            let engine = if args.iter().any(|arg| arg == "alphabeta") {
+                EngineKind::AlphaBeta
+            } else if let Some(level) = args.iter().find_map(|arg| parse_level(arg)) {
+                EngineKind::Level(level)
+            } else {
+                EngineKind::MonteCarlo
+            };
+            let ponder = !args.iter().any(|arg| arg == "noponder");
+            let control = time_control_arg(&args[2..]).unwrap_or(TimeControl::Fischer {
+                base: Duration::from_secs(300),
+                increment: Duration::from_secs(2),
+            });
+            game::play_against_human(human, engine, control, ponder);
+        }
+        Some("match") => {
+            let mut first = player_arg(args.get(2));
+            let mut second = player_arg(args.get(3));
+            let games = args.get(4).and_then(|n| n.parse().ok()).unwrap_or(20);
+            // a bare number after the players is the game count, the time control
+            // follows it or takes its place when it has a '+' or '/'
+            let control = match args.get(4) {
+                Some(arg) if arg.contains('+') || arg.contains('/') => TimeControl::parse(arg),
+                _ => args.get(5).and_then(|arg| TimeControl::parse(arg)),
+            }
+            .unwrap_or(TimeControl::Fischer {
+                base: Duration::from_secs(60),
+                increment: Duration::from_secs(1),
+            });
+            let result = game::play_match(
+                first.as_mut(),
+                second.as_mut(),
+                games,
+                control,
+                |game, record, result| {
+                    println!(
+                        "game {}: {:?} after {} plies, {}-{}-{}",
+                        game,
+                        record.winner,
+                        record.moves.len(),
+                        result.wins,
+                        result.draws,
+                        result.losses
+                    )
+                },
+            );
+            let (low, high) = result.elo_interval();
+            println!(
+                "{}-{}-{} score {:.3} elo difference {:+.0} (95% {:+.0} to {:+.0})",
+                result.wins,
+                result.draws,
+                result.losses,
+                result.score(),
+                result.elo_difference(),
+                low,
+                high
+            );
+        }
+        Some("search") => {
+            let mut config = TreeConfig::default();
+            if let Some(path) = args.iter().find_map(|arg| arg.strip_prefix("net=")) {
+                config = network_config(Path::new(path));
+            }
+            if let Some(size) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("batch=")?.parse().ok())
+            {
+                config = config.with_batch_size(size);
+            }
+            if let Some(selection) = args.iter().skip(3).find_map(|arg| selection::parse(arg)) {
+                config = config.with_selection(selection);
+            }
+            if let Some(rollout) = args
+                .iter()
+                .find_map(|arg| rollout::parse(arg.strip_prefix("rollout=")?))
+            {
+                config = config.with_rollout(rollout);
+            }
+            if let Some(plies) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("cutoff=")?.parse().ok())
+            {
+                config = config.with_rollout_cutoff(plies);
+            }
+            if let Some(equivalence) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("rave=")?.parse().ok())
+            {
+                config = config.with_rave(Rave::new(equivalence));
+            }
+            if let Some(coefficient) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("widen=")?.parse().ok())
+            {
+                config = config.with_widening(Widening {
+                    coefficient,
+                    ..Widening::default()
+                });
+            }
+            if let Some(weight) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("bias=")?.parse().ok())
+            {
+                config = config.with_progressive_bias(weight);
+            }
+            if let Some(final_move) = args
+                .iter()
+                .find_map(|arg| FinalMove::parse(arg.strip_prefix("final=")?))
+            {
+                config = config.with_final_move(final_move);
+            }
+            if let Some(factor) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("extend=")?.parse().ok())
+            {
+                config = config.with_disagreement_extension(factor);
+            }
+            if let Some(megabytes) = args
+                .iter()
+                .find_map(|arg| arg.strip_prefix("cap=")?.parse::<usize>().ok())
+            {
+                config = config.with_memory_cap(megabytes * 1024 * 1024);
+            }
+            if let Some(hybrid) = args
+                .iter()
+                .find_map(|arg| Hybrid::parse(arg.strip_prefix("hybrid=")?))
+            {
+                config = config.with_hybrid(hybrid);
+            }
+            let budget = budget_args(&args[2..]).unwrap_or(SearchBudget::iterations(10000));
+            search_start(
+                args.get(2).and_then(|seed| seed.parse().ok()),
+                config,
+                budget,
+                args.iter().any(|arg| arg == "graph"),
+                export_args(&args[2..]),
+                analyze_arg(&args[2..]),
+            )
+        }
+        _ => search_start(
+            None,
+            TreeConfig::default(),
+            SearchBudget::time(MoveTime::fixed(Duration::from_secs(1))),
+            false,
+            None,
+            None,
+        ),
+    }
+}
+
+// network evaluation with PUCT selection
+fn network_config(path: &Path) -> TreeConfig {
+    let network = Network::load(path).expect("could not read the network");
+    TreeConfig::default()
+        .with_network(Arc::new(network))
+        .with_selection(selection::parse("puct").unwrap())
+}
+
+fn parse_level(arg: &str) -> Option<StrengthLevel> {
+    StrengthLevel::get(arg.strip_prefix("level")?.parse().ok()?)
+}
+
+// the first argument that is a time control
+fn time_control_arg(args: &[String]) -> Option<TimeControl> {
+    args.iter().find_map(|arg| TimeControl::parse(arg))
+}
+
+fn player_arg(arg: Option<&String>) -> Box<dyn Player> {
+    match arg.map(|a| a.as_str()) {
+        Some("alphabeta") => Box::new(AlphaBeta::new()),
+        Some("graph") => Box::new(GraphPlayer::new(TreeConfig::default(), rand::random())),
+        Some(name) if name.starts_with("root:") => {
+            let threads = name["root:".len()..].parse().expect("root:<threads>");
+            Box::new(RootParallelPlayer::new(
+                TreeConfig::default(),
+                threads,
+                rand::random(),
+            ))
+        }
+        Some(name) if name.starts_with("net:") => Box::new(MonteCarloPlayer::new(
+            network_config(Path::new(&name["net:".len()..])),
+            rand::random(),
+        )),
+        Some(name) if name.starts_with("hybrid:") => {
+            let hybrid =
+                Hybrid::parse(&name["hybrid:".len()..]).expect("hybrid:<depth>[:<weight>]");
+            Box::new(MonteCarloPlayer::new(
+                TreeConfig::default().with_hybrid(hybrid),
+                rand::random(),
+            ))
+        }
+        Some(name) if name.starts_with("tree:") => {
+            let threads = name["tree:".len()..].parse().expect("tree:<threads>");
+            Box::new(TreeParallelPlayer::new(
+                TreeConfig::default(),
+                threads,
+                rand::random(),
+            ))
+        }
+        Some(name) if name.contains(',') => {
+            let mut parts = name.split(',');
+            let selection = parts.next().and_then(selection::parse);
+            let rollout = parts.next().and_then(rollout::parse);
+            let mut config = TreeConfig::default()
+                .with_selection(selection.expect("unknown selection policy"))
+                .with_rollout(rollout.expect("unknown rollout policy"));
+            if let Some(plies) = parts.next() {
+                config = config.with_rollout_cutoff(plies.parse().expect("cutoff in plies"));
+            }
+            Box::new(MonteCarloPlayer::new(config, rand::random()))
+        }
+        Some(name) => match (parse_level(name), selection::parse(name)) {
+            (Some(level), _) => Box::new(LevelPlayer::new(level, rand::random())),
+            (_, Some(selection)) => Box::new(MonteCarloPlayer::new(
+                TreeConfig::default().with_selection(selection),
+                rand::random(),
+            )),
+            _ => panic!("unknown player {}", name),
+        },
+        None => Box::new(LevelPlayer::new(
+            StrengthLevel::get(6).unwrap(),
+            rand::random(),
+        )),
+    }
+}
+
+fn self_play(games: usize, book_path: &Path) {
+    let mut book = if book_path.exists() {
+        OpeningBook::load(book_path).expect("could not read opening book")
+    } else {
+        OpeningBook::new()
+    };
+
+    for game_number in 1..=games {
+        let record = game::play_self_play_game(&book, rand::random());
+        book.learn(&record);
+        book.save(book_path).expect("could not write opening book");
+        println!(
+            "game {}: {:?} after {} plies, book has {} positions",
+            game_number,
+            record.winner,
+            record.moves.len(),
+            book.len()
+        );
+    }
+}
+
+fn data_gen(games: usize, dir: &Path, args: &[String]) {
+    let mut config = SelfPlayConfig::default();
+    let mut seed = rand::random();
+    for (key, value) in args.iter().filter_map(|arg| arg.split_once('=')) {
+        match key {
+            "threads" => config.threads = value.parse().expect("threads=<n>"),
+            "iterations" => config.iterations = value.parse().expect("iterations=<n>"),
+            "opening" => config.opening_plies = value.parse().expect("opening=<plies>"),
+            "sampling" => config.sampling_plies = value.parse().expect("sampling=<plies>"),
+            "seed" => seed = value.parse().expect("seed=<n>"),
+            "net" => config.tree = network_config(Path::new(value)),
+            _ => {}
+        }
+    }
+    let now = Instant::now();
+    let generated = training::generate(&config, games, dir, seed, |game, positions| {
+        println!("game {}: {} positions", game, positions)
+    })
+    .expect("could not write the shards");
+    println!(
+        "{} games, {} positions on {} threads in {:.2?}",
+        generated.games,
+        generated.positions,
+        config.threads,
+        now.elapsed()
+    );
+}
+
+// positions, results and policy sizes of the given shards or directories of shards
+fn data_info(paths: &[String]) {
+    let mut shards = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            shards.extend(training::shards_in(path).expect("could not list the shards"));
+        } else {
+            shards.push(path.to_path_buf());
+        }
+    }
+    let (mut positions, mut wins, mut draws, mut moves) = (0, 0, 0, 0);
+    let mut value_error = 0.0;
+    for shard in &shards {
+        let reader = ShardReader::open(shard).expect("could not open the shard");
+        for sample in reader {
+            let sample = sample.expect("could not read the shard");
+            positions += 1;
+            match sample.result {
+                1 => wins += 1,
+                0 => draws += 1,
+                _ => {}
+            }
+            moves += sample.policy.len();
+            value_error += (sample.value - (sample.result as f32 + 1.0) / 2.0).abs();
+        }
+    }
+    println!(
+        "{} shards, {} positions: {} won, {} drawn, {} lost by the side to move",
+        shards.len(),
+        positions,
+        wins,
+        draws,
+        positions - wins - draws
+    );
+    println!(
+        "{:.1} visited moves per position, search value off the result by {:.3} on average",
+        moves as f32 / positions.max(1) as f32,
+        value_error / positions.max(1) as f32
+    );
+}
+
+// key=value search limits, None when no limit was given
+fn budget_args(args: &[String]) -> Option<SearchBudget> {
+    let mut budget = SearchBudget::default();
+    let mut limited = false;
+    for (key, value) in args.iter().filter_map(|arg| arg.split_once('=')) {
+        let value: u64 = match value.parse() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        match key {
+            "iterations" => budget.iterations = Some(value as usize),
+            "time" => budget.time = Some(MoveTime::fixed(Duration::from_millis(value))),
+            "playouts" => budget = budget.with_playouts(value as i32),
+            "memory" => budget = budget.with_memory(value as usize * 1024 * 1024),
+            "stopafter" => {
+                let stop = Arc::new(AtomicBool::new(false));
+                let setter = stop.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(value));
+                    setter.store(true, Ordering::Relaxed);
+                });
+                budget = budget.with_stop(stop);
+            }
+            _ => continue,
+        }
+        limited = true;
+    }
+    limited.then_some(budget)
+}
+
+// export=<file> with its pruning options, None when no file was given
+fn export_args(args: &[String]) -> Option<(&Path, ExportOptions)> {
+    let mut path = None;
+    let mut options = ExportOptions::default();
+    for (key, value) in args.iter().filter_map(|arg| arg.split_once('=')) {
+        match key {
+            "export" => path = Some(Path::new(value)),
+            "topk" => options.top_k = value.parse().ok(),
+            "minvisits" => options.min_visits = value.parse().unwrap_or(0),
+            "maxdepth" => options.max_depth = value.parse().ok(),
+            _ => {}
+        }
+    }
+    Some((path?, options))
+}
+
+// analyze or analyze=<z>, the normal quantile of the intervals
+fn analyze_arg(args: &[String]) -> Option<f32> {
+    args.iter().find_map(|arg| match arg.split_once('=') {
+        Some(("analyze", z)) => z.parse().ok(),
+        None if arg == "analyze" => Some(analysis::DEFAULT_Z),
+        _ => None,
+    })
+}
+
+fn print_analysis(analysis: &Analysis) {
+    println!("{:<22} {:>7} {:>9}  interval", "move", "visits", "win rate");
+    for analysis in &analysis.moves {
+        println!(
+            "{:<22} {:>7} {:>9.3}  {:.3} - {:.3}",
+            format!("{:?}", analysis.action),
+            analysis.visits,
+            analysis.win_rate,
+            analysis.lower,
+            analysis.upper
+        );
+    }
+    println!(
+        "best move {:?} is {}separated from the others",
+        analysis.best,
+        if analysis.separated { "" } else { "not " }
+    );
+}
+
+// Times the tree search with full playouts and with playouts cut off after two
+// moves, where selection, expansion and backpropagation take most of the time.
+fn bench(iterations: usize) {
+    let configs = [
+        ("full playouts", TreeConfig::default()),
+        ("cutoff=2", TreeConfig::default().with_rollout_cutoff(2)),
+    ];
+    for (name, config) in configs {
+        let mut board = Board::new(Color::Black);
+        board.reset();
+        let mut tree = Tree::with_config(board, 42, config);
+        let now = Instant::now();
+        let playouts = tree
+            .search(&SearchBudget::iterations(iterations))
+            .unwrap_or(0);
+        let elapsed = now.elapsed();
+        println!(
+            "{:<14} {} playouts in {:.2?}, {:.0} playouts/s",
+            name,
+            playouts,
+            elapsed,
+            playouts as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+// with a seed and a budget that does not depend on time the search replays exactly
+fn search_start(
+    seed: Option<u64>,
+    config: TreeConfig,
+    budget: SearchBudget,
+    graph: bool,
+    export: Option<(&Path, ExportOptions)>,
+    analyze: Option<f32>,
+) {
     let mut board = Board::new(Color::Black);
     board.reset();
-    let mut tree = Tree::new(board);
+    board.print_board();
 
     let now = Instant::now();
-    println!("best move {:?}", tree.get_monte_carlo_move());
+    let seed = seed.unwrap_or_else(rand::random);
+    if graph {
+        assert!(
+            export.is_none(),
+            "export= needs a tree, it does not work with graph"
+        );
+        let mut graph = Graph::with_config(board, seed, config);
+        let iterations = graph.search(&budget).unwrap_or(0);
+        println!(
+            "expanded {} times, {} positions, {} transpositions",
+            iterations,
+            graph.positions(),
+            graph.transpositions()
+        );
+        println!(
+            "best move {:?}, graph uses {} KB",
+            graph.select_best_move(),
+            graph.memory_usage() / 1024
+        );
+        println!("took: {:.2?}", now.elapsed());
+        if let Some(analysis) = analyze.and_then(|z| graph.analyze(z)) {
+            print_analysis(&analysis);
+        }
+        return;
+    }
+    let mut tree = Tree::with_config(board, seed, config);
+    match tree.search(&budget) {
+        Some(i) => println!("expanded {} times", i),
+        None => {
+            println!("no legal moves");
+            return;
+        }
+    }
+    let best = tree.select_best_move();
+    println!(
+        "best move {:?}, tree uses {} KB ({} nodes of {} bytes)",
+        best,
+        tree.memory_usage() / 1024,
+        tree.node_count(),
+        Tree::bytes_per_node()
+    );
     let elapsed = now.elapsed();
-    println!("took: {:.2?}", elapsed)
+    println!("took: {:.2?}", elapsed);
+    if let Some(analysis) = analyze.and_then(|z| tree.analyze(z)) {
+        print_analysis(&analysis);
+    }
+
+    if let Some((path, options)) = export {
+        let nodes = tree.export(&options);
+        export::write(&nodes, path).expect("could not write the tree");
+        println!("wrote {} nodes to {}", nodes.len(), path.display());
+    }
 
     // use indextree::Arena;
     // let mut arena = Arena::new();
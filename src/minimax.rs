@@ -1,3 +1,210 @@
-fn main() {
-    println!("Hello, world!");
+use std::time::{Duration, Instant};
+
+use crate::board::{Action, Board, Color, MoveMemHandler, Piece};
+use crate::clock::MoveTime;
+
+const MAN_VALUE: i32 = 100;
+const KING_VALUE: i32 = 160;
+const ADVANCE_BONUS: i32 = 2;
+pub const WIN_SCORE: i32 = 100_000;
+const MAX_DEPTH: usize = 64;
+// nodes between checks of the clock
+const CHECK_INTERVAL: u64 = 1024;
+
+// static evaluation from the point of view of the side to move
+pub fn evaluate(board: &Board) -> i32 {
+    let us = board.get_current_color();
+    let mut score = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Some(Piece::Filled(color, king)) = board.get_piece(x, y) {
+                let value = if king {
+                    KING_VALUE
+                } else {
+                    let advanced = match color {
+                        Color::Black => y,
+                        Color::Red => 7 - y,
+                    };
+                    MAN_VALUE + ADVANCE_BONUS * advanced as i32
+                };
+                score += if color == us { value } else { -value };
+            }
+        }
+    }
+    score
+}
+
+// whether `score` comes from the end of the game rather than the evaluation
+pub fn is_decided(score: i32) -> bool {
+    score.abs() >= WIN_SCORE - MAX_DEPTH as i32
+}
+
+// Iterative deepening alpha-beta search.
+pub struct AlphaBeta {
+    move_handler: MoveMemHandler,
+    nodes: u64,
+    start: Instant,
+    time: MoveTime,
+    stopped: bool,
+}
+
+impl AlphaBeta {
+    pub fn new() -> Self {
+        Self {
+            move_handler: MoveMemHandler::new(),
+            nodes: 0,
+            start: Instant::now(),
+            time: MoveTime::fixed(Duration::ZERO),
+            stopped: false,
+        }
+    }
+
+    pub fn get_best_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        self.start = Instant::now();
+        self.time = time;
+        self.stopped = false;
+        self.nodes = 0;
+
+        board.get_all_actions(&mut self.move_handler);
+        let actions = self.move_handler.actions();
+        let mut best = actions.get(0);
+        let mut instability = 0.0;
+
+        for depth in 1..=MAX_DEPTH {
+            let mut alpha = -WIN_SCORE - 1;
+            let mut depth_best = best;
+            // search the previous best move first so a cut off iteration is still useful
+            let ordered = std::iter::once(best).chain(
+                (0..actions.len())
+                    .map(|i| actions.get(i))
+                    .filter(|a| *a != best),
+            );
+            for action in ordered {
+                let score = self.score_child(board, action, depth - 1, alpha, WIN_SCORE + 1, 1);
+                if self.stopped {
+                    break;
+                }
+                if score > alpha {
+                    alpha = score;
+                    depth_best = action;
+                }
+            }
+            if self.stopped {
+                break;
+            }
+            if depth_best != best {
+                instability += 1.0;
+            }
+            best = depth_best;
+
+            // the next iteration takes several times longer than this one, only
+            // start it when there is a fair chance of finishing
+            let elapsed = self.start.elapsed();
+            if actions.len() <= 1 || is_decided(alpha) {
+                break;
+            }
+            if self.time.should_stop(elapsed * 2, instability) {
+                break;
+            }
+            instability *= 0.5;
+        }
+        best
+    }
+
+    // plays `action` and returns the negamax score of the result for the side that played it
+    fn score_child(
+        &mut self,
+        board: &Board,
+        action: Action,
+        depth: usize,
+        alpha: i32,
+        beta: i32,
+        ply: i32,
+    ) -> i32 {
+        let mut child = board.clone();
+        child.execute_action(action);
+        // after a capture the same side may still be on move
+        if child.get_current_color() == board.get_current_color() {
+            self.negamax(&child, depth, alpha, beta, ply)
+        } else {
+            -self.negamax(&child, depth, -beta, -alpha, ply)
+        }
+    }
+
+    fn negamax(&mut self, board: &Board, depth: usize, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        self.nodes += 1;
+        if self.nodes % CHECK_INTERVAL == 0 && self.start.elapsed() >= self.time.hard {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+
+        board.get_all_actions(&mut self.move_handler);
+        if !self.move_handler.has_actions() {
+            return -WIN_SCORE + ply;
+        }
+        if depth == 0 {
+            return evaluate(board);
+        }
+
+        let actions = self.move_handler.actions();
+        for i in 0..actions.len() {
+            let score = self.score_child(board, actions.get(i), depth - 1, alpha, beta, ply + 1);
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+// Fixed depth alpha-beta without a clock, small enough to run for every new
+// node of the Monte Carlo tree. Past the depth it keeps following captures
+// (quiescence): they are forced, so the evaluation in the middle of an
+// exchange would miss the pieces about to be taken back.
+pub struct ShallowSearch {
+    move_handler: MoveMemHandler,
+}
+
+impl ShallowSearch {
+    pub fn new() -> Self {
+        Self {
+            move_handler: MoveMemHandler::new(),
+        }
+    }
+
+    // negamax score of `board` for the side to move, `depth` plies deep
+    pub fn score(&mut self, board: &Board, depth: usize) -> i32 {
+        self.negamax(board, depth, -WIN_SCORE - 1, WIN_SCORE + 1, 0)
+    }
+
+    fn negamax(&mut self, board: &Board, depth: usize, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        board.get_all_actions(&mut self.move_handler);
+        if !self.move_handler.has_actions() {
+            return -WIN_SCORE + ply;
+        }
+        if depth == 0 && !self.move_handler.contains_capture() {
+            return evaluate(board);
+        }
+
+        let actions = self.move_handler.actions();
+        for i in 0..actions.len() {
+            let mut child = board.clone();
+            child.execute_action(actions.get(i));
+            let depth = depth.saturating_sub(1);
+            // after a capture the same side may still be on move
+            let score = if child.get_current_color() == board.get_current_color() {
+                self.negamax(&child, depth, alpha, beta, ply + 1)
+            } else {
+                -self.negamax(&child, depth, -beta, -alpha, ply + 1)
+            };
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
 }
@@ -1,263 +1,1323 @@
-use rand::{
-    prelude::{IteratorRandom, SliceRandom},
-    Rng,
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use crate::board::{Action, Board, Color, MoveMemHandler, StaticList};
-use indextree::{Arena, NodeId};
+use bit_vec::BitVec;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
+use crate::analysis::Analysis;
+use crate::board::{Action, Board, Color, MoveMemHandler, PackedAction};
+use crate::clock::MoveTime;
+use crate::export::{ExportOptions, ExportedNode};
+use crate::minimax::{self, ShallowSearch};
+use crate::network::{self, Evaluation, Network};
+use crate::rollout::{self, FeatureWeights, Outcome, RolloutPolicy, UniformRollout};
+use crate::selection::{ChildStats, ParentStats, Rave, SelectionPolicy, Ucb1};
+use indextree::{Arena, Node, NodeId};
+
+// Nodes hold no board, the position of a node is rebuilt by replaying the moves
+// from the root's board, and the move is packed, which keeps a node small.
 #[derive(Clone, Copy)]
 struct NodeState {
-    board: NodeId,
     sims: i32,
-    wins: i32,
-    action_taken: Option<Action>,
-    loc: Option<NodeId>,
+    // fractional once playouts are cut off and scored by the evaluation
+    wins: f32,
+    action_taken: Option<PackedAction>,
+    // side that played `action_taken`, wins are counted for it
+    mover: Option<Color>,
+    // prior probability of the move leading here, uniform over the parent's moves
+    prior: f32,
+    // MCTS-Solver: the side that wins from here with best play, once the search proved it
+    proven: Option<Color>,
+    // all-moves-as-first statistics of `action_taken`, see `Rave`
+    amaf_sims: i32,
+    amaf_wins: f32,
+    // heuristic value of `action_taken`, only computed for widening and bias
+    heuristic: f32,
+    // hybrid search: chance that `mover` wins by the shallow alpha-beta search,
+    // replaced by the best child's value once the node has children
+    minimax: f32,
+    // children added so far and the number of legal moves, None until expanded;
+    // with progressive widening the children can be fewer than the moves
+    width: u16,
+    move_count: Option<u16>,
+}
+
+// expansions between checks of whether the best move changed
+const CHECK_INTERVAL: usize = 256;
+// how quickly past changes of the best move stop counting as instability
+const INSTABILITY_DECAY: f32 = 0.9;
+// share of the memory cap that is left in use after pruning, so pruning
+// happens in batches instead of after every expansion
+const PRUNE_TARGET: f32 = 0.75;
+
+// search options fixed when the tree is built
+#[derive(Clone)]
+pub struct TreeConfig {
+    pub selection: Arc<dyn SelectionPolicy>,
+    pub rollout: Arc<dyn RolloutPolicy>,
+    // playouts stop after this many moves and are scored by the evaluation
+    pub rollout_cutoff: Option<usize>,
+    pub rave: Option<Rave>,
+    pub widening: Option<Widening>,
+    // weight of the progressive bias term heuristic / (sims + 1)
+    pub progressive_bias: Option<f32>,
+    pub final_move: FinalMove,
+    // when the most simulated and the best valued root moves differ once the
+    // budget is used up, keep searching for up to this fraction of the budget
+    // more, so 0.5 allows one and a half times the budget
+    pub disagreement_extension: Option<f32>,
+    // bytes the tree may hold, above it the least simulated subtrees are pruned
+    pub memory_cap: Option<usize>,
+    // scores leaves with the network instead of playouts and takes the priors
+    // of new children from its policy, best used with PUCT selection
+    pub network: Option<Arc<Network>>,
+    // leaves collected for one network evaluation
+    pub batch_size: usize,
+    // shallow alpha-beta searches of new nodes and minimax backups, see `Hybrid`
+    pub hybrid: Option<Hybrid>,
+}
+
+// how the move to play is picked from the root's children once the search is over
+#[derive(Clone, Copy, Debug, Default)]
+pub enum FinalMove {
+    // the most simulated child
+    #[default]
+    MaxVisits,
+    // the child with the best win rate
+    MaxValue,
+    // the child that is both, the most simulated one when they differ
+    RobustMax,
+    // the best lower bound win rate - a / sqrt(sims)
+    SecureChild {
+        a: f32,
+    },
+}
+
+// Progressive widening: a node only gets its best `coefficient * (sims + 1)^exponent`
+// moves by heuristic order as children, more are added as its simulations grow.
+#[derive(Clone, Copy, Debug)]
+pub struct Widening {
+    pub coefficient: f32,
+    pub exponent: f32,
+}
+
+// Hybrid search: every new child is scored by an alpha-beta search of `depth`
+// plies with quiescence over captures, which proves it won or lost outright when
+// the game ends within the search and so catches the tactics random playouts
+// miss. The scores are backed up minimax style along each simulated path
+// (implicit minimax backups) and selection uses
+// (1 - minimax_weight) * win rate + minimax_weight * minimax value,
+// with the minimax value alone for children that were not visited yet.
+#[derive(Clone, Copy, Debug)]
+pub struct Hybrid {
+    pub depth: usize,
+    pub minimax_weight: f32,
+}
+
+// Limits for one search, it ends as soon as any of them is reached.
+// With nothing set the search only ends through the stop flag.
+#[derive(Clone, Default)]
+pub struct SearchBudget {
+    pub iterations: Option<usize>,
+    pub time: Option<MoveTime>,
+    // simulations at the root, including any carried over from pondering
+    pub playouts: Option<i32>,
+    pub memory_bytes: Option<usize>,
+    // set from another thread (e.g. a GUI) to end the search early,
+    // the best move found so far is still returned
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+// where a search stands, checked against its budget
+pub(crate) struct SearchProgress {
+    pub iterations: usize,
+    pub elapsed: Duration,
+    pub instability: f32,
+    pub root_sims: i32,
+    pub memory_bytes: usize,
+}
+
+// statistics of one move at the root, wins are for the side to move at the root
+#[derive(Clone, Copy, Debug)]
+pub struct RootChild {
+    pub action: Action,
+    pub sims: i32,
+    pub wins: f32,
 }
 
-const UCT_CONST: f32 = 1.141;
 pub struct Tree {
     root: NodeId,
     arena: Arena<NodeState>,
-    board_arena: Arena<Board>,
+    root_board: Board,
     move_handler: MoveMemHandler,
+    // every random choice of the search comes from here,
+    // so the same seed replays the same search
+    rng: SmallRng,
+    config: TreeConfig,
+    // live nodes; freed slots are reused by the arena
+    nodes: usize,
+    // moves of the current simulation for the AMAF update, kept to reuse its memory
+    trace: Vec<(Color, Action)>,
+    // the same moves as one bit per side and packed move, see `amaf_index`
+    played: BitVec,
+    // nodes of the current simulation from the root down, likewise
+    path: Vec<NodeId>,
+    shallow: ShallowSearch,
+    unwidened: Unwidened,
+}
+
+// (move, heuristic, prior) in the order progressive widening adds children
+type OrderedMove = (PackedAction, f32, f32);
+
+// Moves of partially widened nodes that are not children yet, best first, so
+// widening further takes the next ones instead of scoring every move again.
+#[derive(Default)]
+struct Unwidened {
+    moves: HashMap<NodeId, Vec<OrderedMove>>,
+    // moves held over all nodes, for the memory use
+    len: usize,
+}
+
+impl Unwidened {
+    fn insert(&mut self, node_id: NodeId, moves: Vec<OrderedMove>) {
+        if !moves.is_empty() {
+            self.len += moves.len();
+            self.moves.insert(node_id, moves);
+        }
+    }
+
+    fn take(&mut self, node_id: NodeId) -> Vec<OrderedMove> {
+        let moves = self.moves.remove(&node_id).unwrap_or_default();
+        self.len -= moves.len();
+        moves
+    }
+}
+
+// bit of a (side, move) pair in `Tree::played`, a packed move has 13 bits
+fn amaf_index(side: Color, action: PackedAction) -> usize {
+    (side as usize) << 13 | action.bits() as usize
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        Self {
+            selection: Arc::new(Ucb1::default()),
+            rollout: Arc::new(UniformRollout),
+            rollout_cutoff: None,
+            rave: None,
+            widening: None,
+            progressive_bias: None,
+            final_move: FinalMove::default(),
+            disagreement_extension: None,
+            memory_cap: None,
+            network: None,
+            batch_size: 8,
+            hybrid: None,
+        }
+    }
+}
+
+impl SearchBudget {
+    pub fn iterations(iterations: usize) -> Self {
+        Self {
+            iterations: Some(iterations),
+            ..Self::default()
+        }
+    }
+
+    pub fn time(time: MoveTime) -> Self {
+        Self {
+            time: Some(time),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_playouts(mut self, playouts: i32) -> Self {
+        self.playouts = Some(playouts);
+        self
+    }
+
+    pub fn with_memory(mut self, bytes: usize) -> Self {
+        self.memory_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    // the same limits stretched by 1 + `factor`, except that the hard time limit stays
+    fn extended(&self, factor: f32) -> Self {
+        let stretch = 1.0 + factor;
+        Self {
+            iterations: self.iterations.map(|n| (n as f32 * stretch) as usize),
+            time: self.time.map(|time| MoveTime {
+                soft: time.soft.mul_f32(stretch).min(time.hard),
+                hard: time.hard,
+            }),
+            playouts: self.playouts.map(|n| (n as f32 * stretch) as i32),
+            memory_bytes: self.memory_bytes,
+            stop: self.stop.clone(),
+        }
+    }
+
+    pub(crate) fn exhausted(&self, progress: &SearchProgress) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || self
+                .iterations
+                .is_some_and(|limit| progress.iterations >= limit)
+            || self
+                .time
+                .is_some_and(|time| time.should_stop(progress.elapsed, progress.instability))
+            || self
+                .playouts
+                .is_some_and(|limit| progress.root_sims >= limit)
+            || self
+                .memory_bytes
+                .is_some_and(|limit| progress.memory_bytes >= limit)
+    }
+
+    // simulations left before the iteration or playout limit, so a batch does not overshoot
+    fn remaining(&self, progress: &SearchProgress) -> usize {
+        let iterations = self.iterations.map_or(usize::MAX, |limit| {
+            limit.saturating_sub(progress.iterations)
+        });
+        let playouts = self.playouts.map_or(usize::MAX, |limit| {
+            limit.saturating_sub(progress.root_sims).max(0) as usize
+        });
+        iterations.min(playouts)
+    }
+}
+
+impl TreeConfig {
+    pub fn with_selection(mut self, selection: Arc<dyn SelectionPolicy>) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_rollout(mut self, rollout: Arc<dyn RolloutPolicy>) -> Self {
+        self.rollout = rollout;
+        self
+    }
+
+    pub fn with_rollout_cutoff(mut self, plies: usize) -> Self {
+        self.rollout_cutoff = Some(plies);
+        self
+    }
+
+    pub fn with_rave(mut self, rave: Rave) -> Self {
+        self.rave = Some(rave);
+        self
+    }
+
+    pub fn with_widening(mut self, widening: Widening) -> Self {
+        self.widening = Some(widening);
+        self
+    }
+
+    pub fn with_progressive_bias(mut self, weight: f32) -> Self {
+        self.progressive_bias = Some(weight);
+        self
+    }
+
+    pub fn with_final_move(mut self, final_move: FinalMove) -> Self {
+        self.final_move = final_move;
+        self
+    }
+
+    pub fn with_disagreement_extension(mut self, factor: f32) -> Self {
+        self.disagreement_extension = Some(factor);
+        self
+    }
+
+    pub fn with_memory_cap(mut self, bytes: usize) -> Self {
+        self.memory_cap = Some(bytes);
+        self
+    }
+
+    pub fn with_network(mut self, network: Arc<Network>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    pub fn with_hybrid(mut self, hybrid: Hybrid) -> Self {
+        self.hybrid = Some(hybrid);
+        self
+    }
+
+    // moves are scored by the heuristic only when something uses the scores
+    fn needs_heuristic(&self) -> bool {
+        self.widening.is_some() || self.progressive_bias.is_some()
+    }
+}
+
+impl FinalMove {
+    // "visits", "value", "robust", "secure" or "secure:2"
+    pub fn parse(text: &str) -> Option<FinalMove> {
+        let (name, constant) = match text.split_once(':') {
+            Some((name, constant)) => (name, Some(constant.parse::<f32>().ok()?)),
+            None => (text, None),
+        };
+        match name {
+            "visits" => Some(FinalMove::MaxVisits),
+            "value" => Some(FinalMove::MaxValue),
+            "robust" => Some(FinalMove::RobustMax),
+            "secure" => Some(FinalMove::SecureChild {
+                a: constant.unwrap_or(1.0),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Widening {
+    fn default() -> Self {
+        Self {
+            coefficient: 2.0,
+            exponent: 0.5,
+        }
+    }
+}
+
+impl Widening {
+    // how many children a node with `sims` simulations may have, at least one
+    fn width(&self, sims: i32) -> usize {
+        ((self.coefficient * (sims.max(0) as f32 + 1.0).powf(self.exponent)) as usize).max(1)
+    }
+}
+
+impl Default for Hybrid {
+    fn default() -> Self {
+        Self {
+            depth: 2,
+            minimax_weight: 0.3,
+        }
+    }
+}
+
+impl Hybrid {
+    // "3" or "3:0.5", the depth and optionally the minimax weight
+    pub fn parse(text: &str) -> Option<Hybrid> {
+        let (depth, weight) = match text.split_once(':') {
+            Some((depth, weight)) => (depth, Some(weight.parse::<f32>().ok()?)),
+            None => (text, None),
+        };
+        Some(Hybrid {
+            depth: depth.parse().ok()?,
+            minimax_weight: weight.unwrap_or(Hybrid::default().minimax_weight),
+        })
+    }
+
+    // `child` with its wins replaced so that wins / sims is the blended mean.
+    // An unvisited child counts as one visit at its minimax value, so the shallow
+    // search orders the first visits; weight 0 leaves selection to the win rates.
+    fn blend(&self, child: ChildStats, minimax: f32) -> ChildStats {
+        if self.minimax_weight == 0.0 {
+            return child;
+        }
+        if child.sims == 0 {
+            return ChildStats {
+                wins: minimax,
+                sims: 1,
+                ..child
+            };
+        }
+        let mean = child.wins / child.sims as f32;
+        let blended = (1.0 - self.minimax_weight) * mean + self.minimax_weight * minimax;
+        ChildStats {
+            wins: blended * child.sims as f32,
+            ..child
+        }
+    }
 }
 
 impl Tree {
-    pub fn get_monte_carlo_move(&mut self) -> Action {
-        let root = self.arena.get(self.root).unwrap().get();
-        self.board_arena
-            .get(root.board)
-            .unwrap()
-            .get()
-            .get_all_actions(&mut self.move_handler);
+    // expands the root if needed, false when the root position has no moves
+    fn prepare_root(&mut self) -> bool {
+        self.root_board.get_all_actions(&mut self.move_handler);
 
         if !self.move_handler.has_actions() {
-            println!("returning first move");
-            return self.move_handler.get(0);
+            return false;
         }
-        root.expand(
-            &mut self.arena,
-            &mut self.board_arena,
-            &mut self.move_handler,
-        );
+        // a pondered tree arrives with its root already expanded
+        if self.root.children(&self.arena).next().is_none() {
+            let evaluation = self.config.network.as_ref().map(|network| {
+                network
+                    .evaluate(std::slice::from_ref(&self.root_board))
+                    .pop()
+                    .unwrap()
+            });
+            let added = NodeState::expand(
+                self.root,
+                &mut self.arena,
+                &self.root_board,
+                &mut self.move_handler,
+                &mut self.unwidened,
+                &self.config,
+                evaluation.as_ref(),
+            );
+            self.nodes += added;
+            let board = self.root_board.clone();
+            self.score_new_children(self.root, &board, added);
+        }
+        true
+    }
+
+    pub fn root_proven(&self) -> Option<Color> {
+        self.arena.get(self.root).unwrap().get().proven
+    }
+
+    fn root_sims(&self) -> i32 {
+        self.arena.get(self.root).unwrap().get().sims
+    }
+
+    // approximate bytes held by the tree's live nodes and their moves left to widen
+    pub fn memory_usage(&self) -> usize {
+        self.nodes * Self::bytes_per_node() + self.unwidened.len * mem::size_of::<OrderedMove>()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes
+    }
+
+    // one node with the arena's links around it
+    pub fn bytes_per_node() -> usize {
+        mem::size_of::<Node<NodeState>>()
+    }
+
+    // re-roots the tree at the child reached by `action`, keeping its statistics and
+    // freeing the old root and every other subtree, call it for both sides' moves
+    // returns false when that child was never expanded and the tree should be rebuilt
+    pub fn advance(&mut self, action: Action) -> bool {
+        let new_root = match self
+            .root
+            .children(&self.arena)
+            .find(|id| self.arena.get(*id).unwrap().get().action_taken == Some(action.pack()))
+        {
+            Some(child) => child,
+            None => return false,
+        };
+
+        new_root.detach(&mut self.arena);
+        self.root_board.execute_action(action);
+        let old_root = self.root;
+        self.root = new_root;
+        self.free_subtree(old_root);
+        true
+    }
+
+    // the root move leading to `board`, used to find the opponent's move after our own
+    pub fn find_move_to(&self, board: &Board) -> Option<Action> {
+        let hash = board.hash();
+        self.root.children(&self.arena).find_map(|id| {
+            let action = self.arena.get(id).unwrap().get().action_taken?.unpack();
+            let mut child_board = self.root_board.clone();
+            child_board.execute_action(action);
+            (child_board.hash() == hash).then_some(action)
+        })
+    }
+
+    fn free_subtree(&mut self, top: NodeId) {
+        let mut count = 0;
+        for node_id in top.descendants(&self.arena) {
+            self.unwidened.take(node_id);
+            count += 1;
+        }
+        top.remove_subtree(&mut self.arena);
+        self.nodes -= count;
+    }
+
+    // Frees the children of the least simulated nodes until the tree fits in
+    // `target` bytes. A pruned node keeps its own statistics and proof and is
+    // expanded again if the search returns to it. The root's children stay.
+    fn prune(&mut self, target: usize) {
+        let mut candidates: Vec<(i32, NodeId)> = self
+            .root
+            .descendants(&self.arena)
+            .filter(|id| *id != self.root && id.children(&self.arena).next().is_some())
+            .map(|id| (self.arena.get(id).unwrap().get().sims, id))
+            .collect();
+        candidates.sort_by_key(|&(sims, _)| sims);
+        for (_, id) in candidates {
+            if self.memory_usage() <= target {
+                break;
+            }
+            // already freed with a pruned ancestor
+            if id.is_removed(&self.arena) {
+                continue;
+            }
+            let children: Vec<NodeId> = id.children(&self.arena).collect();
+            for child in children {
+                self.free_subtree(child);
+            }
+            self.unwidened.take(id);
+            let node = self.arena.get_mut(id).unwrap().get_mut();
+            node.width = 0;
+            node.move_count = None;
+        }
+    }
+
+    // expands the tree until the budget runs out and returns the number of expansions,
+    // None when the root has no moves
+    pub fn search(&mut self, budget: &SearchBudget) -> Option<usize> {
+        if !self.prepare_root() {
+            return None;
+        }
+        let start = Instant::now();
+        let mut best = self.select_best_move();
+        let mut instability = 0.0;
         let mut i = 0;
-        for _ in 0..10000 {
-            self.expand_tree();
-            i += 1;
+        let mut next_check = CHECK_INTERVAL;
+        let extended = self
+            .config
+            .disagreement_extension
+            .map(|factor| budget.extended(factor));
+        // once the root is proven more searching cannot change the move
+        while self.root_proven().is_none() {
+            let progress = SearchProgress {
+                iterations: i,
+                elapsed: start.elapsed(),
+                instability,
+                root_sims: self.root_sims(),
+                memory_bytes: self.memory_usage(),
+            };
+            // the budget in force, the extended one once the original is used up
+            let in_force = match &extended {
+                Some(extended) if budget.exhausted(&progress) => {
+                    if extended.exhausted(&progress)
+                        || self.select_move(FinalMove::MaxVisits)
+                            == self.select_move(FinalMove::MaxValue)
+                    {
+                        break;
+                    }
+                    extended
+                }
+                _ if budget.exhausted(&progress) => break,
+                _ => budget,
+            };
+            i += self.expand_tree(in_force.remaining(&progress).max(1));
+            if let Some(cap) = self.config.memory_cap {
+                if self.memory_usage() > cap {
+                    self.prune((cap as f32 * PRUNE_TARGET) as usize);
+                }
+            }
+
+            // batches can step over a multiple of the interval
+            if i >= next_check {
+                next_check = i + CHECK_INTERVAL;
+                let current = self.select_best_move();
+                if current != best {
+                    instability += 1.0;
+                }
+                instability *= INSTABILITY_DECAY;
+                best = current;
+            }
         }
-        println!("expanded {} times", i);
-        return self.select_best_move();
+        Some(i)
     }
 
-    pub fn new(board: Board) -> Self {
-        let mut board_arena = Arena::<Board>::new();
-        let root = NodeState::new(board, &mut board_arena);
+    // the root's moves with their statistics, most simulated first
+    pub fn root_children(&self) -> Vec<RootChild> {
+        let mut children: Vec<RootChild> = self
+            .root
+            .children(&self.arena)
+            .map(|id| {
+                let node = self.arena.get(id).unwrap().get();
+                RootChild {
+                    action: node.action_taken.unwrap().unpack(),
+                    sims: node.sims,
+                    wins: node.wins,
+                }
+            })
+            .collect();
+        children.sort_by_key(|child| std::cmp::Reverse(child.sims));
+        children
+    }
+
+    // the root's moves with confidence intervals from the normal quantile `z`,
+    // None before a search has expanded the root or when it has no moves
+    pub fn analyze(&self, z: f32) -> Option<Analysis> {
+        self.root.children(&self.arena).next()?;
+        let mut analysis = Analysis::new(&self.root_children(), self.select_best_move(), z);
+        // a proven root leaves nothing to find out by searching longer
+        analysis.separated |= self.root_proven().is_some();
+        Some(analysis)
+    }
+
+    // searches within the budget, None when the root has no legal moves
+    pub fn get_monte_carlo_move(&mut self, budget: &SearchBudget) -> Option<Action> {
+        self.search(budget)?;
+        Some(self.select_best_move())
+    }
+
+    pub fn with_seed(board: Board, seed: u64) -> Self {
+        Self::with_config(board, seed, TreeConfig::default())
+    }
+
+    pub fn with_config(board: Board, seed: u64, config: TreeConfig) -> Self {
         let mut arena = Arena::new();
-        let root_id = arena.new_node(root);
-        arena.get_mut(root_id).unwrap().get_mut().set_loc(root_id);
+        let root_id = arena.new_node(NodeState::new());
         Self {
             root: root_id,
-            arena: arena,
-            board_arena: board_arena,
+            arena,
+            root_board: board,
             move_handler: MoveMemHandler::new(),
+            rng: SmallRng::seed_from_u64(seed),
+            config,
+            nodes: 1,
+            trace: Vec::new(),
+            played: BitVec::from_elem(2 << 13, false),
+            path: Vec::new(),
+            shallow: ShallowSearch::new(),
+            unwidened: Unwidened::default(),
         }
     }
 
+    // the move picked by the configured final move strategy
     pub fn select_best_move(&self) -> Action {
-        let children = self.root.children(&self.arena);
+        self.select_move(self.config.final_move)
+    }
+
+    // a proven win is always played and a proven loss only when nothing else
+    // is left, otherwise `strategy` decides
+    pub fn select_move(&self, strategy: FinalMove) -> Action {
+        let us = self.root_board().get_current_color();
+        let children: Vec<NodeState> = self
+            .root
+            .children(&self.arena)
+            .map(|id| *self.arena.get(id).unwrap().get())
+            .collect();
+        let value = |node: &NodeState| node.wins / node.sims.max(1) as f32;
+        let most_visited = children.iter().map(|node| node.sims).max().unwrap_or(0);
+        let best_value = children.iter().map(value).fold(f32::MIN, f32::max);
 
-        let max = children.into_iter().max_by(|node_id, node_id2| {
-            self.arena
-                .get(*node_id)
+        // (robust, score) so a robust child beats every other under RobustMax
+        let key = |node: &NodeState| -> (bool, f32) {
+            match strategy {
+                FinalMove::MaxVisits => (false, node.sims as f32),
+                FinalMove::MaxValue => (false, value(node)),
+                FinalMove::RobustMax => (
+                    node.sims == most_visited && value(node) == best_value,
+                    node.sims as f32,
+                ),
+                FinalMove::SecureChild { a } => {
+                    (false, value(node) - a / (node.sims.max(1) as f32).sqrt())
+                }
+            }
+        };
+        let best = children.iter().max_by(|x, y| {
+            let x_proof = (x.proven == Some(us), x.proven != Some(us.opposite()));
+            let y_proof = (y.proven == Some(us), y.proven != Some(us.opposite()));
+            let (x_robust, x_score) = key(x);
+            let (y_robust, y_score) = key(y);
+            x_proof
+                .cmp(&y_proof)
+                .then(x_robust.cmp(&y_robust))
+                .then(x_score.total_cmp(&y_score))
+        });
+        best.unwrap().action_taken.unwrap().unpack()
+    }
+
+    // the explored tree in depth first order, pruned by `options`
+    pub fn export(&self, options: &ExportOptions) -> Vec<ExportedNode> {
+        let mut nodes: Vec<ExportedNode> = Vec::new();
+        let mut stack = vec![(self.root, None, 0, self.root_board.clone())];
+        while let Some((id, parent, depth, board)) = stack.pop() {
+            let node = self.arena.get(id).unwrap().get();
+            let index = nodes.len();
+            let uct = parent.map(|parent: usize| {
+                node.uct_value(&ParentStats::new(nodes[parent].visits), &self.config)
+            });
+            nodes.push(ExportedNode {
+                parent,
+                action: node.action_taken.map(PackedAction::unpack),
+                visits: node.sims,
+                wins: node.wins,
+                uct,
+                depth,
+                proven: node.proven,
+                board: board.snapshot(),
+            });
+            if options.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            let mut children: Vec<NodeId> = id
+                .children(&self.arena)
+                .filter(|child| self.arena.get(*child).unwrap().get().sims >= options.min_visits)
+                .collect();
+            children
+                .sort_by_key(|child| std::cmp::Reverse(self.arena.get(*child).unwrap().get().sims));
+            children.truncate(options.top_k.unwrap_or(usize::MAX));
+            // reversed so the most simulated child is popped, and listed, first
+            for child in children.into_iter().rev() {
+                let mut child_board = board.clone();
+                let action = self.arena.get(child).unwrap().get().action_taken.unwrap();
+                child_board.execute_action(action.unpack());
+                stack.push((child, Some(index), depth + 1, child_board));
+            }
+        }
+        nodes
+    }
+
+    pub fn root_board(&self) -> &Board {
+        &self.root_board
+    }
+
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    // the reply to `action` the search currently expects, i.e. the most
+    // simulated child below the root child reached by `action`
+    pub fn expected_reply(&self, action: Action) -> Option<Action> {
+        let child = self
+            .root
+            .children(&self.arena)
+            .find(|id| self.arena.get(*id).unwrap().get().action_taken == Some(action.pack()))?;
+        let reply = child
+            .children(&self.arena)
+            .max_by_key(|id| self.arena.get(*id).unwrap().get().sims)?;
+        Some(self.arena.get(reply).unwrap().get().action_taken?.unpack())
+    }
+
+    // one simulation, or one batch of at most `limit` with a network,
+    // returns the number of simulations made
+    pub fn expand_tree(&mut self, limit: usize) -> usize {
+        if self.root_proven().is_some() {
+            return 0;
+        }
+        if let Some(network) = self.config.network.clone() {
+            return self.evaluate_batch(&network, limit);
+        }
+        let mut board = self.select();
+        let promising_node_id = *self.path.last().unwrap();
+
+        let added = NodeState::expand(
+            promising_node_id,
+            &mut self.arena,
+            &board,
+            &mut self.move_handler,
+            &mut self.unwidened,
+            &self.config,
+            None,
+        );
+        self.nodes += added;
+        if added == 0 {
+            self.settle_lost(promising_node_id, &board);
+        }
+        self.score_new_children(promising_node_id, &board, added);
+
+        // the playout starts from one of the children just added, which are the last ones
+        if added > 0 {
+            let children = promising_node_id.children(&self.arena).count();
+            let index = self.rng.gen_range(children - added..children);
+            let test_node = promising_node_id.children(&self.arena).nth(index).unwrap();
+            let action = self
+                .arena
+                .get(test_node)
                 .unwrap()
                 .get()
-                .sims
-                .cmp(&self.arena.get(*node_id2).unwrap().get().sims)
-        });
-        return self
-            .arena
-            .get(max.unwrap())
-            .unwrap()
-            .get()
-            .action_taken
-            .unwrap();
+                .action_taken
+                .unwrap();
+            board.execute_action(action.unpack());
+            self.path.push(test_node);
+        }
+
+        self.trace.clear();
+        let trace = self.config.rave.is_some().then_some(&mut self.trace);
+        let outcome = rollout::play_out(
+            &mut board,
+            &*self.config.rollout,
+            self.config.rollout_cutoff,
+            &mut self.move_handler,
+            &mut self.rng,
+            trace,
+        );
+        self.back_propagate(outcome);
+        if self.config.rave.is_some() {
+            self.update_amaf(outcome);
+        }
+        if self.config.hybrid.is_some() {
+            self.back_up_minimax();
+        }
+        1
     }
 
-    pub fn expand_tree(&mut self) {
-        let mut arena = &mut self.arena;
-        let promising_node_id = arena.get(self.root).unwrap().get().select_node(&arena);
+    // no moves: the side to move has lost, which settles this node for good
+    fn settle_lost(&mut self, node_id: NodeId, board: &Board) {
+        let winner = board.get_current_color().opposite();
+        self.arena.get_mut(node_id).unwrap().get_mut().proven = Some(winner);
+        self.propagate_proof(node_id);
+    }
 
-        let promising_node = arena.get_mut(promising_node_id).unwrap().get_mut();
+    // hybrid search: scores the `added` children just appended to `node_id`
+    // with the shallow search and settles those it proves
+    fn score_new_children(&mut self, node_id: NodeId, board: &Board, added: usize) {
+        let depth = match self.config.hybrid {
+            Some(hybrid) => hybrid.depth,
+            None => return,
+        };
+        let children: Vec<NodeId> = node_id.children(&self.arena).collect();
+        for &child in &children[children.len() - added..] {
+            let node = self.arena.get(child).unwrap().get();
+            let mover = node.mover.unwrap();
+            let mut child_board = board.clone();
+            child_board.execute_action(node.action_taken.unwrap().unpack());
+            let score = self.shallow.score(&child_board, depth);
+            // the score is for the side to move, the mover again after a capture
+            let side = child_board.get_current_color();
+            let chance = rollout::win_chance(score);
+            let node = self.arena.get_mut(child).unwrap().get_mut();
+            node.minimax = if side == mover { chance } else { 1.0 - chance };
+            if minimax::is_decided(score) && node.proven.is_none() {
+                node.proven = Some(if score > 0 { side } else { side.opposite() });
+                self.propagate_proof(child);
+            }
+        }
+    }
 
-        promising_node.expand(&mut arena, &mut self.board_arena, &mut self.move_handler);
+    // Selects up to `batch_size` leaves, fewer when `limit` is lower near the end
+    // of the budget, evaluates them with one call to the network, then expands
+    // each with the policy as priors and backs up the value. While the batch is
+    // collected every node on a selected path carries a virtual loss, a simulation
+    // without a win, so the next selection tends to a different leaf; collecting
+    // stops early when a leaf comes up twice.
+    fn evaluate_batch(&mut self, network: &Network, limit: usize) -> usize {
+        let batch_size = self.config.batch_size.min(limit);
+        let mut leaves: Vec<(Vec<NodeId>, Board)> = Vec::with_capacity(batch_size);
+        while leaves.len() < batch_size {
+            let board = self.select();
+            let leaf = self.path.last();
+            if leaves.iter().any(|(path, _)| path.last() == leaf) {
+                break;
+            }
+            for &node_id in &self.path {
+                self.arena.get_mut(node_id).unwrap().get_mut().sims += 1;
+            }
+            leaves.push((self.path.clone(), board));
+        }
 
-        let children = promising_node_id.children(&self.arena).into_iter();
+        let boards: Vec<Board> = leaves.iter().map(|(_, board)| board.clone()).collect();
+        let evaluations = network.evaluate(&boards);
+        let simulations = leaves.len();
+        for ((path, board), evaluation) in leaves.into_iter().zip(evaluations) {
+            for &node_id in &path {
+                self.arena.get_mut(node_id).unwrap().get_mut().sims -= 1;
+            }
+            let leaf = *path.last().unwrap();
+            let added = NodeState::expand(
+                leaf,
+                &mut self.arena,
+                &board,
+                &mut self.move_handler,
+                &mut self.unwidened,
+                &self.config,
+                Some(&evaluation),
+            );
+            self.nodes += added;
+            self.score_new_children(leaf, &board, added);
+            let side = board.get_current_color();
+            let outcome = if added == 0 {
+                self.settle_lost(leaf, &board);
+                Outcome::win(side.opposite())
+            } else {
+                Outcome::estimate(side, (evaluation.value + 1.0) / 2.0)
+            };
+            self.path = path;
+            self.back_propagate(outcome);
+            if self.config.rave.is_some() {
+                self.trace.clear();
+                self.update_amaf(outcome);
+            }
+            if self.config.hybrid.is_some() {
+                self.back_up_minimax();
+            }
+        }
+        simulations
+    }
 
-        let mut list = StaticList::new();
+    // Descends from the root to the node to expand, taking the unproven child
+    // with the best uct value at each level, and stops early at a node that
+    // progressive widening wants to give another child. The nodes passed are
+    // left in `path`, root first, and the position reached is returned.
+    fn select(&mut self) -> Board {
+        let mut board = self.root_board.clone();
+        let mut node_id = self.root;
+        self.path.clear();
+        self.path.push(node_id);
+        loop {
+            let node = self.arena.get(node_id).unwrap().get();
+            if node.can_widen(&self.config) {
+                return board;
+            }
+            let parent = ParentStats::new(node.sims);
+            // the last of equal values wins, as with Iterator::max_by
+            let mut best: Option<(NodeId, f32)> = None;
+            for child_id in node_id.children(&self.arena) {
+                let child = self.arena.get(child_id).unwrap().get();
+                if child.proven.is_some() {
+                    continue;
+                }
+                let value = child.uct_value(&parent, &self.config);
+                if best.is_none_or(|(_, best_value)| value >= best_value) {
+                    best = Some((child_id, value));
+                }
+            }
+            let (child_id, _) = match best {
+                Some(best) => best,
+                None => return board,
+            };
+            let action = self.arena.get(child_id).unwrap().get().action_taken;
+            board.execute_action(action.unwrap().unpack());
+            self.path.push(child_id);
+            node_id = child_id;
+        }
+    }
 
-        for child in children {
-            list.push(child);
+    // counts the simulation in every node on the path, for the side that moved into it
+    fn back_propagate(&mut self, outcome: Outcome) {
+        for &node_id in &self.path {
+            let node = self.arena.get_mut(node_id).unwrap().get_mut();
+            node.sims += 1;
+            if let Some(mover) = node.mover {
+                node.wins += outcome.value_for(mover);
+            }
         }
+    }
+}
 
-        let children = list;
+impl Tree {
+    // Implicit minimax backups: from the bottom of the path up, every node with
+    // children takes the value of its best child, a proven child counting as
+    // a sure win or loss.
+    fn back_up_minimax(&mut self) {
+        for &node_id in self.path.iter().rev() {
+            let mover = match self.arena.get(node_id).unwrap().get().mover {
+                Some(mover) => mover,
+                None => continue,
+            };
+            // the children's mover and the best value for it
+            let mut best: Option<(Color, f32)> = None;
+            for child in node_id.children(&self.arena) {
+                let child = self.arena.get(child).unwrap().get();
+                let side = child.mover.unwrap();
+                let value = match child.proven {
+                    Some(winner) => (winner == side) as u8 as f32,
+                    None => child.minimax,
+                };
+                if best.is_none_or(|(_, best)| value > best) {
+                    best = Some((side, value));
+                }
+            }
+            if let Some((side, value)) = best {
+                let node = self.arena.get_mut(node_id).unwrap().get_mut();
+                node.minimax = if side == mover { value } else { 1.0 - value };
+            }
+        }
+    }
 
-        let mut test_node = promising_node_id;
+    // Walks the path from the simulated node back to the root. At every node on
+    // the way, each child whose move the side to move played later in the simulation
+    // (in the tree below or in the playout) gets the result as an AMAF sample.
+    fn update_amaf(&mut self, outcome: Outcome) {
+        self.played.clear();
+        for &(side, action) in &self.trace {
+            self.played.set(amaf_index(side, action.pack()), true);
+        }
+        for (depth, &node_id) in self.path.iter().enumerate().rev() {
+            let mut next = self.arena.get(node_id).unwrap().first_child();
+            while let Some(child) = next {
+                let child_node = self.arena.get_mut(child).unwrap();
+                next = child_node.next_sibling();
+                let child_node = child_node.get_mut();
+                // the children's mover is the side to move here
+                if let (Some(action), Some(us)) = (child_node.action_taken, child_node.mover) {
+                    if self.played[amaf_index(us, action)] {
+                        child_node.amaf_sims += 1;
+                        child_node.amaf_wins += outcome.value_for(us);
+                    }
+                }
+            }
 
-        if children.len() > 0 {
-            //     println!(
-            //         "number of children {} nodeId {}",
-            //         children.len(),
-            //         promising_node_id
-            //     );
-            let index = rand::thread_rng().gen_range(0..children.len());
-            test_node = children.get(index);
+            if depth > 0 {
+                let node = self.arena.get(node_id).unwrap().get();
+                let index = amaf_index(node.mover.unwrap(), node.action_taken.unwrap());
+                self.played.set(index, true);
+            }
         }
+    }
 
-        self.arena.get(test_node).unwrap().get().play_out(
-            &mut self.arena,
-            &mut self.board_arena,
-            &mut self.move_handler,
-        );
+    // A node is won for the side to move if one of its moves is proven to win
+    // for that side, and lost if all of its moves are proven to lose (which needs
+    // every move to have a child, see progressive widening). Walks up from the
+    // parent of a newly proven node while proofs keep appearing.
+    fn propagate_proof(&mut self, proven: NodeId) {
+        let mut node_id = proven;
+        while let Some(parent) = self.arena.get(node_id).unwrap().parent() {
+            let parent_node = self.arena.get(parent).unwrap().get();
+            let us = self.arena.get(node_id).unwrap().get().mover.unwrap();
+            // moves that were not widened into children yet are unproven
+            let mut all_lost = parent_node.move_count == Some(parent_node.width);
+            let mut won = false;
+            for child in parent.children(&self.arena) {
+                match self.arena.get(child).unwrap().get().proven {
+                    Some(winner) if winner == us => won = true,
+                    Some(_) => {}
+                    None => all_lost = false,
+                }
+            }
+            let proof = if won {
+                us
+            } else if all_lost {
+                us.opposite()
+            } else {
+                return;
+            };
+            self.arena.get_mut(parent).unwrap().get_mut().proven = Some(proof);
+            node_id = parent;
+        }
     }
 }
 
-impl<'a, 'b> NodeState {
-    pub fn new(board: Board, arena: &mut Arena<Board>) -> Self {
+impl NodeState {
+    pub fn new() -> Self {
         Self {
-            board: arena.new_node(board),
             sims: 0,
-            wins: 0,
+            wins: 0.0,
             action_taken: None,
-            loc: None,
+            mover: None,
+            prior: 1.0,
+            proven: None,
+            amaf_sims: 0,
+            amaf_wins: 0.0,
+            heuristic: 0.0,
+            minimax: 0.5,
+            width: 0,
+            move_count: None,
         }
     }
 
-    pub fn new_child(board: NodeId, action: Action, board_arena: &mut Arena<Board>) -> Self {
-        let mut board = board_arena.get(board).unwrap().get().clone();
-        board.execute_action(action);
+    pub fn new_child(action: Action, mover: Color, prior: f32, heuristic: f32) -> Self {
         Self {
-            board: board_arena.new_node(board),
             sims: 0,
-            wins: 0,
-            action_taken: Some(action),
-            loc: None,
+            wins: 0.0,
+            action_taken: Some(action.pack()),
+            mover: Some(mover),
+            prior,
+            proven: None,
+            amaf_sims: 0,
+            amaf_wins: 0.0,
+            heuristic,
+            minimax: 0.5,
+            width: 0,
+            move_count: None,
         }
     }
 
-    fn set_loc(&mut self, loc: NodeId) {
-        self.loc = Some(loc)
+    // adds children for the node's moves and returns how many were added,
+    // with progressive widening only the next best ones up to the allowed width
+    // with an evaluation the priors come from its policy, otherwise they are uniform
+    fn expand(
+        node_id: NodeId,
+        arena: &mut Arena<NodeState>,
+        board: &Board,
+        move_mem: &mut MoveMemHandler,
+        unwidened: &mut Unwidened,
+        config: &TreeConfig,
+        evaluation: Option<&Evaluation>,
+    ) -> usize {
+        let node = arena.get(node_id).unwrap().get();
+        let start = node.width as usize;
+        let sims = node.sims;
+        // widening an expanded node further continues with the moves its first expansion left
+        let mut moves = match node.move_count {
+            Some(_) => unwidened.take(node_id),
+            None => Self::ordered_moves(board, move_mem, config, evaluation),
+        };
+        let len = start + moves.len();
+        let end = match config.widening {
+            Some(widening) => widening.width(sims).max(start + 1).min(len),
+            None => len,
+        };
+
+        let rest = moves.split_off(end - start);
+        for (action, heuristic, prior) in moves {
+            let new_child = arena.new_node(NodeState::new_child(
+                action.unpack(),
+                board.get_current_color(),
+                prior,
+                heuristic,
+            ));
+            node_id.append(new_child, arena);
+        }
+        unwidened.insert(node_id, rest);
+        let node = arena.get_mut(node_id).unwrap().get_mut();
+        node.width = end as u16;
+        node.move_count = Some(len as u16);
+        end - start
     }
 
-    pub fn expand(
-        self,
-        arena: &'a mut Arena<NodeState>,
-        board_arena: &'b mut Arena<Board>,
+    // the node's moves with their heuristic and prior, best heuristic first when
+    // widening or bias needs it
+    fn ordered_moves(
+        board: &Board,
         move_mem: &mut MoveMemHandler,
-    ) {
-        // println!("expanding on node {:?}", self.loc);
-        board_arena
-            .get(self.board)
-            .unwrap()
-            .get()
-            .get_all_actions(move_mem);
+        config: &TreeConfig,
+        evaluation: Option<&Evaluation>,
+    ) -> Vec<OrderedMove> {
+        board.get_all_actions(move_mem);
         let len = move_mem.len();
-        for index in 0..len {
-            let action = move_mem.get(index);
-            let new_child = arena.new_node(NodeState::new_child(self.board, action, board_arena));
-            arena
-                .get_mut(new_child)
-                .unwrap()
-                .get_mut()
-                .set_loc(new_child);
-            self.loc.unwrap().append(new_child, arena);
+        let actions: Vec<Action> = (0..len).map(|i| move_mem.get(i)).collect();
+        let priors = match evaluation {
+            Some(evaluation) => network::priors(evaluation, board.get_current_color(), &actions),
+            None => vec![1.0 / len as f32; len],
+        };
+
+        let mut moves: Vec<OrderedMove> = actions
+            .into_iter()
+            .zip(priors)
+            .map(|(action, prior)| (action.pack(), 0.0, prior))
+            .collect();
+        if config.needs_heuristic() {
+            let features = FeatureWeights::default();
+            for (action, heuristic, _) in moves.iter_mut() {
+                *heuristic = features.score(board, action.unpack());
+            }
+            moves.sort_by(|a, b| b.1.total_cmp(&a.1));
         }
+        moves
     }
 
-    pub fn uct_value(&self, node_id: NodeId, arena: &Arena<NodeState>) -> f32 {
-        //NOTE: might want to implement caching of uct values
-        let parent_sims = match arena.get(node_id).unwrap().parent() {
-            Some(parent) => arena.get(parent).unwrap().get().sims,
-            None => 1,
-        };
-        if self.sims == 0 {
-            return f32::INFINITY;
+    // whether progressive widening wants another child added here
+    fn can_widen(&self, config: &TreeConfig) -> bool {
+        match (config.widening, self.move_count) {
+            (Some(widening), Some(moves)) => {
+                self.width < moves && widening.width(self.sims) > self.width as usize
+            }
+            _ => false,
         }
-        self.wins as f32 / self.sims as f32
-            + (UCT_CONST * ((parent_sims as f32) / (self.sims as f32)))
-                .log2()
-                .sqrt()
     }
 
-    pub fn play_out(
-        self,
-        arena: &mut Arena<NodeState>,
-        board_arena: &Arena<Board>,
-        move_mem: &mut MoveMemHandler,
-    ) {
-        let mut copy_board = board_arena.get(self.board).unwrap().get().clone();
-        let mut winner = None;
+    fn uct_value(&self, parent: &ParentStats, config: &TreeConfig) -> f32 {
+        let stats = match config.rave {
+            Some(rave) => rave.blend(self.stats(), self.amaf_wins, self.amaf_sims),
+            None => self.stats(),
+        };
+        let stats = match config.hybrid {
+            Some(hybrid) => hybrid.blend(stats, self.minimax),
+            None => stats,
+        };
+        let bias = match config.progressive_bias {
+            Some(weight) => weight * self.heuristic / (self.sims + 1) as f32,
+            None => 0.0,
+        };
+        config.selection.score(&stats, parent) + bias
+    }
 
-        while winner == None {
-            winner = copy_board.make_random_move(move_mem);
+    fn stats(&self) -> ChildStats {
+        ChildStats {
+            wins: self.wins,
+            sims: self.sims,
+            prior: self.prior,
         }
-        self.back_propagate(winner.unwrap(), arena, board_arena);
     }
+}
 
-    pub(crate) fn back_propagate(
-        self,
-        winning: Color,
-        arena: &mut Arena<NodeState>,
-        board_arena: &Arena<Board>,
-    ) {
-        let board = board_arena.get(self.board).unwrap().get();
-        let mut self_node = arena.get_mut(self.loc.unwrap()).unwrap().get_mut();
-
-        match board.get_last_turn() {
-            Some(last_turn) => {
-                if last_turn == winning {
-                    self_node.wins += 1;
-                }
-            }
-            None => {}
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Piece;
+    use crate::game;
+
+    // best move and node count of a seeded search of the start
+    fn seeded_search(seed: u64, iterations: usize) -> (Action, usize) {
+        let mut tree = Tree::with_seed(game::starting_board(), seed);
+        tree.search(&SearchBudget::iterations(iterations));
+        (tree.select_best_move(), tree.node_count())
+    }
 
-        self_node.sims += 1;
+    #[test]
+    fn the_same_seed_replays_the_same_search() {
+        assert_eq!(seeded_search(42, 2000), seeded_search(42, 2000));
+    }
 
-        match arena.get(self.loc.unwrap()).unwrap().parent() {
-            Some(parent) => {
-                arena
-                    .get(parent)
-                    .unwrap()
-                    .get()
-                    .back_propagate(winning, arena, board_arena)
-            }
-            None => {}
-        }
+    fn piece(color: Color, king: bool) -> Piece {
+        Piece::Filled(color, king)
     }
 
-    pub fn select_node(&self, arena: &Arena<NodeState>) -> NodeId {
-        let select = self
-            .loc
-            .unwrap()
-            .children(arena)
-            .into_iter()
-            .max_by(|node1, node2| {
-                arena
-                    .get(*node1)
-                    .unwrap()
-                    .get()
-                    .uct_value(*node1, arena)
-                    .partial_cmp(&arena.get(*node2).unwrap().get().uct_value(*node2, arena))
-                    .unwrap()
+    // searches `board` until the solver proves it, with or without widening
+    fn solve(board: Board, widen: bool) -> (Tree, usize) {
+        let mut config = TreeConfig::default();
+        if widen {
+            config = config.with_widening(Widening {
+                coefficient: 1.0,
+                ..Widening::default()
             });
+        }
+        let mut tree = Tree::with_config(board, 7, config);
+        let iterations = tree.search(&SearchBudget::iterations(100_000)).unwrap();
+        (tree, iterations)
+    }
 
-        match select {
-            Some(node_id) => {
-                let new_node = arena.get(node_id).unwrap().get();
-                new_node.select_node(arena)
-            }
-            None => self.loc.unwrap(),
+    #[test]
+    fn the_solver_proves_a_one_move_win() {
+        // Black wins by 0,0-1,1, which leaves the red man without a move;
+        // 2,0-1,1 and 2,0-3,1 do not
+        let mut board = Board::new(Color::Black);
+        board.set_piece(0, 0, piece(Color::Black, false));
+        board.set_piece(2, 0, piece(Color::Black, false));
+        board.set_piece(0, 2, piece(Color::Red, false));
+        for widen in [false, true] {
+            let (tree, iterations) = solve(board.clone(), widen);
+            assert_eq!(tree.root_proven(), Some(Color::Black), "widen {}", widen);
+            assert!(iterations < 100_000, "widen {}", widen);
+            assert_eq!(tree.select_best_move(), Action::Move(0, 0, 1, 1));
+        }
+    }
+
+    #[test]
+    fn the_solver_proves_a_position_where_every_move_loses() {
+        // either move of the red king can be jumped
+        let mut board = Board::new(Color::Red);
+        board.set_piece(0, 2, piece(Color::Red, true));
+        board.set_piece(2, 0, piece(Color::Black, false));
+        board.set_piece(2, 2, piece(Color::Black, false));
+        for widen in [false, true] {
+            let (tree, iterations) = solve(board.clone(), widen);
+            assert_eq!(tree.root_proven(), Some(Color::Black), "widen {}", widen);
+            assert!(iterations < 100_000, "widen {}", widen);
         }
     }
+
+    #[test]
+    fn pruning_keeps_the_tree_under_the_memory_cap() {
+        let cap = 1024 * 1024;
+        let config = TreeConfig::default().with_memory_cap(cap);
+        let mut tree = Tree::with_config(game::starting_board(), 42, config);
+        // about 6 MB without the cap
+        tree.search(&SearchBudget::iterations(10000));
+        assert!(tree.memory_usage() <= cap);
+        assert!(
+            tree.root_children()
+                .iter()
+                .map(|child| child.sims)
+                .sum::<i32>()
+                >= 10000
+        );
+    }
 }
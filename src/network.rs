@@ -0,0 +1,252 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::board::{Action, Board, Color, Piece};
+
+// own men, own kings, enemy men and enemy kings on the 32 dark squares
+pub const INPUTS: usize = 4 * 32;
+// a move from one of the 32 dark squares in one of 4 directions, as a step or a jump
+pub const POLICY_SIZE: usize = 32 * 4 * 2;
+
+const MAGIC: &str = "checkers-net";
+
+// A small policy/value network: one hidden layer of ReLU units feeding
+// a tanh value head and a policy head with one logit per move shape.
+// Positions are seen from the side to move, so the network plays both colours.
+//
+// Weight file, plain text: a "checkers-net <hidden>" line, then one line per
+// tensor with its numbers separated by spaces, in the order hidden weights
+// (hidden x INPUTS, row by row), hidden biases, value weights, value bias,
+// policy weights (POLICY_SIZE x hidden, row by row) and policy biases.
+pub struct Network {
+    hidden: usize,
+    hidden_weights: Vec<f32>,
+    hidden_biases: Vec<f32>,
+    value_weights: Vec<f32>,
+    value_bias: f32,
+    policy_weights: Vec<f32>,
+    policy_biases: Vec<f32>,
+}
+
+// what the network makes of one position, for the side to move
+pub struct Evaluation {
+    // from -1 (lost) to 1 (won)
+    pub value: f32,
+    // logits indexed by `policy_index`, only those of legal moves mean anything
+    pub policy: Vec<f32>,
+}
+
+impl Network {
+    // small random weights, a starting point for training
+    pub fn random(hidden: usize, seed: u64) -> Self {
+        assert!(hidden > 0, "a network needs at least one hidden unit");
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut weights = |count: usize, fan_in: usize| -> Vec<f32> {
+            let range = (1.0 / fan_in as f32).sqrt();
+            (0..count).map(|_| rng.gen_range(-range..range)).collect()
+        };
+        Self {
+            hidden,
+            hidden_weights: weights(hidden * INPUTS, INPUTS),
+            hidden_biases: vec![0.0; hidden],
+            value_weights: weights(hidden, hidden),
+            value_bias: 0.0,
+            policy_weights: weights(POLICY_SIZE * hidden, hidden),
+            policy_biases: vec![0.0; POLICY_SIZE],
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let hidden = lines
+            .next()
+            .and_then(|line| line.strip_prefix(MAGIC))
+            .and_then(|size| size.trim().parse::<usize>().ok())
+            .ok_or_else(|| invalid(format!("{} is not a network file", path.display())))?;
+        if hidden == 0 {
+            return Err(invalid(format!("{} has no hidden units", path.display())));
+        }
+
+        let sizes = [
+            ("hidden weights", hidden * INPUTS),
+            ("hidden biases", hidden),
+            ("value weights", hidden),
+            ("value bias", 1),
+            ("policy weights", POLICY_SIZE * hidden),
+            ("policy biases", POLICY_SIZE),
+        ];
+        let mut tensors = Vec::with_capacity(sizes.len());
+        for (name, size) in sizes {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid(format!("missing {}", name)))?;
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| invalid(format!("bad number in the {}", name)))?;
+            if values.len() != size {
+                return Err(invalid(format!(
+                    "{} {} where {} were expected",
+                    values.len(),
+                    name,
+                    size
+                )));
+            }
+            tensors.push(values);
+        }
+        let mut tensors = tensors.into_iter();
+        let mut next = || tensors.next().unwrap();
+        Ok(Self {
+            hidden,
+            hidden_weights: next(),
+            hidden_biases: next(),
+            value_weights: next(),
+            value_bias: next()[0],
+            policy_weights: next(),
+            policy_biases: next(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        writeln!(file, "{} {}", MAGIC, self.hidden)?;
+        let value_bias = [self.value_bias];
+        for tensor in [
+            &self.hidden_weights[..],
+            &self.hidden_biases,
+            &self.value_weights,
+            &value_bias,
+            &self.policy_weights,
+            &self.policy_biases,
+        ] {
+            let line: Vec<String> = tensor.iter().map(|value| value.to_string()).collect();
+            writeln!(file, "{}", line.join(" "))?;
+        }
+        file.flush()
+    }
+
+    // Evaluates the boards together: each row of weights is read once for the
+    // whole batch instead of once per position, which is what makes batches
+    // of leaves cheaper than evaluating them one by one.
+    pub fn evaluate(&self, boards: &[Board]) -> Vec<Evaluation> {
+        let batch = boards.len();
+        let mut inputs = vec![0.0; batch * INPUTS];
+        for (board, input) in boards.iter().zip(inputs.chunks_mut(INPUTS)) {
+            encode(board, input);
+        }
+
+        let mut hidden = vec![0.0; batch * self.hidden];
+        for unit in 0..self.hidden {
+            let row = &self.hidden_weights[unit * INPUTS..(unit + 1) * INPUTS];
+            for (sample, input) in inputs.chunks(INPUTS).enumerate() {
+                let sum = self.hidden_biases[unit] + dot(row, input);
+                hidden[sample * self.hidden + unit] = sum.max(0.0);
+            }
+        }
+
+        let mut evaluations: Vec<Evaluation> = hidden
+            .chunks(self.hidden)
+            .map(|units| Evaluation {
+                value: (self.value_bias + dot(&self.value_weights, units)).tanh(),
+                policy: vec![0.0; POLICY_SIZE],
+            })
+            .collect();
+        for logit in 0..POLICY_SIZE {
+            let row = &self.policy_weights[logit * self.hidden..(logit + 1) * self.hidden];
+            for (evaluation, units) in evaluations.iter_mut().zip(hidden.chunks(self.hidden)) {
+                evaluation.policy[logit] = self.policy_biases[logit] + dot(row, units);
+            }
+        }
+        evaluations
+    }
+}
+
+// eight running sums instead of one so the compiler can keep them in a vector register
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut sums = [0.0; 8];
+    let (a_chunks, b_chunks) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: f32 = (a_chunks.remainder().iter())
+        .zip(b_chunks.remainder())
+        .map(|(a, b)| a * b)
+        .sum();
+    for (a, b) in a_chunks.zip(b_chunks) {
+        for lane in 0..8 {
+            sums[lane] += a[lane] * b[lane];
+        }
+    }
+    sums.iter().sum::<f32>() + tail
+}
+
+// index of the dark square x, y among the 32, turned round for Red
+// so that the side to move always plays up the board
+fn square_index(x: usize, y: usize, side: Color) -> usize {
+    let (x, y) = match side {
+        Color::Black => (x, y),
+        Color::Red => (7 - x, 7 - y),
+    };
+    y * 4 + x / 2
+}
+
+// the board as network inputs, seen from the side to move
+pub fn encode(board: &Board, input: &mut [f32]) {
+    input.fill(0.0);
+    let side = board.get_current_color();
+    for y in 0..8 {
+        for x in (y % 2..8).step_by(2) {
+            if let Some(Piece::Filled(color, king)) = board.get_piece(x, y) {
+                let plane = (color != side) as usize * 2 + king as usize;
+                input[plane * 32 + square_index(x, y, side)] = 1.0;
+            }
+        }
+    }
+}
+
+// the policy output for `action` played by `side`
+pub fn policy_index(action: Action, side: Color) -> usize {
+    let (x, y, nx, ny, jump) = match action {
+        Action::Move(x, y, nx, ny) => (x, y, nx, ny, 0),
+        Action::Capture(x, y, nx, ny, _, _) => (x, y, nx, ny, 1),
+    };
+    let (right, up) = match side {
+        Color::Black => (nx > x, ny > y),
+        Color::Red => (nx < x, ny < y),
+    };
+    let direction = right as usize + 2 * up as usize;
+    (square_index(x, y, side) * 4 + direction) * 2 + jump
+}
+
+// the policy over `actions` as probabilities, a softmax of their logits
+pub fn priors(evaluation: &Evaluation, side: Color, actions: &[Action]) -> Vec<f32> {
+    let logits: Vec<f32> = actions
+        .iter()
+        .map(|action| evaluation.policy[policy_index(*action, side)])
+        .collect();
+    let best = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let weights: Vec<f32> = logits.iter().map(|logit| (logit - best).exp()).collect();
+    let total: f32 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_network_without_hidden_units_does_not_load() {
+        let path = std::env::temp_dir().join(format!("network-test-{}.txt", std::process::id()));
+        fs::write(&path, format!("{} 0\n", MAGIC)).unwrap();
+        let error = Network::load(&path)
+            .err()
+            .expect("loaded a network without hidden units");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
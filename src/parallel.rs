@@ -0,0 +1,79 @@
+use std::thread;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::board::{Action, Board};
+use crate::clock::MoveTime;
+use crate::game::Player;
+use crate::montecarlo::{RootChild, SearchBudget, Tree, TreeConfig};
+
+// Root parallelism: every thread grows its own independent tree from the same
+// root with its own seed, then the root statistics are summed and the most
+// simulated move overall is played. No state is shared while searching.
+// Each tree checks the budget on its own, so a memory ceiling applies per thread.
+pub fn root_parallel_search(
+    board: &Board,
+    threads: usize,
+    seed: u64,
+    config: &TreeConfig,
+    budget: &SearchBudget,
+) -> Vec<RootChild> {
+    let mut seeds = SmallRng::seed_from_u64(seed);
+    let results: Vec<Vec<RootChild>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                let mut tree = Tree::with_config(board.clone(), seeds.gen(), config.clone());
+                scope.spawn(move || {
+                    tree.search(budget);
+                    tree.root_children()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("search thread panicked"))
+            .collect()
+    });
+
+    let mut merged: Vec<RootChild> = Vec::new();
+    for child in results.into_iter().flatten() {
+        match merged.iter_mut().find(|m| m.action == child.action) {
+            Some(existing) => {
+                existing.sims += child.sims;
+                existing.wins += child.wins;
+            }
+            None => merged.push(child),
+        }
+    }
+    merged.sort_by_key(|child| std::cmp::Reverse(child.sims));
+    merged
+}
+
+pub struct RootParallelPlayer {
+    config: TreeConfig,
+    threads: usize,
+    rng: SmallRng,
+}
+
+impl RootParallelPlayer {
+    pub fn new(config: TreeConfig, threads: usize, seed: u64) -> Self {
+        Self {
+            config,
+            threads,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for RootParallelPlayer {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        let children = root_parallel_search(
+            board,
+            self.threads,
+            self.rng.gen(),
+            &self.config,
+            &SearchBudget::time(time),
+        );
+        children[0].action
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::board::Action;
+use crate::montecarlo::{SearchBudget, Tree};
+
+// the opponent may take far longer than a normal search, stop growing the tree
+// at this size so a long think does not exhaust memory
+const MAX_PONDER_MEMORY: usize = 256 * 1024 * 1024;
+
+// Searches on the opponent's time.
+// After the engine moves it guesses the opponent's reply, advances its own tree
+// past that reply and keeps expanding it on a background thread, so the
+// statistics it already has below the reply are kept. If the guess was right
+// (a ponder hit) the tree is handed back and the next search carries on from
+// it, otherwise (a miss) the tree is thrown away.
+pub struct Ponderer {
+    expected: Action,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Tree>,
+}
+
+impl Ponderer {
+    // `tree` is the engine's tree rooted at the position the opponent is to move in,
+    // `seed` starts a new tree when the expected reply was never expanded in it
+    pub fn start(mut tree: Tree, expected: Action, seed: u64) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let handle = thread::spawn(move || {
+            if !tree.advance(expected) {
+                let mut board = tree.root_board().clone();
+                board.execute_action(expected);
+                tree = Tree::with_config(board, seed, tree.config().clone());
+            }
+            let budget = SearchBudget::default()
+                .with_memory(MAX_PONDER_MEMORY)
+                .with_stop(thread_stop);
+            tree.search(&budget);
+            tree
+        });
+
+        Self {
+            expected,
+            stop,
+            handle,
+        }
+    }
+
+    pub fn expected(&self) -> Action {
+        self.expected
+    }
+
+    // stops the background search once the opponent has played,
+    // returns the pondered tree on a ponder hit
+    pub fn finish(self, played: Action) -> Option<Tree> {
+        self.stop.store(true, Ordering::Relaxed);
+        let tree = self.handle.join().expect("ponder thread panicked");
+        if played == self.expected {
+            Some(tree)
+        } else {
+            None
+        }
+    }
+}
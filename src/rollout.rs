@@ -0,0 +1,341 @@
+use std::sync::Arc;
+
+use rand::{rngs::SmallRng, Rng};
+
+use crate::board::{Action, Board, Color, MoveMemHandler, Piece, StaticList};
+use crate::minimax;
+
+// evaluation difference that makes a cut off playout a 73% (1 / (1 + e^-1)) win,
+// a little more than one man
+const EVALUATION_SCALE: f32 = 120.0;
+
+// Picks the moves of a playout. Uniformly random moves make the results of
+// a playout very noisy, a policy that prefers sensible moves gives the tree
+// more reliable win rates at the cost of slower playouts.
+pub trait RolloutPolicy: Send + Sync {
+    // `moves` holds the legal moves of `board`, at least one
+    fn choose(&self, board: &Board, moves: &MoveMemHandler, rng: &mut SmallRng) -> Action;
+}
+
+// every legal move equally likely, the classic light playout
+pub struct UniformRollout;
+
+// Scores every move by a few features and picks among them by `choice`.
+pub struct HeuristicRollout {
+    pub weights: FeatureWeights,
+    pub choice: RolloutChoice,
+}
+
+// Wraps a policy with checks for moves that end the game on the spot. A move
+// that leaves the opponent without moves is always played (decisive), and with
+// `anti_decisive` the moves that let the opponent do the same right after are
+// avoided while any other move is left. The checks generate the moves of every
+// position one move (two with anti_decisive) ahead, so playouts get a lot slower.
+pub struct DecisiveRollout {
+    pub inner: Arc<dyn RolloutPolicy>,
+    pub anti_decisive: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FeatureWeights {
+    // a man reaching the far row
+    pub promotion: f32,
+    // the moved piece cannot be jumped right away
+    pub safe: f32,
+    // a man leaving its own back row, which opens it to enemy promotions
+    pub leaves_back_rank: f32,
+    // per piece taken by the capture and the jumps it allows afterwards
+    pub capture_length: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RolloutChoice {
+    // probability proportional to exp(score / temperature)
+    Softmax { temperature: f32 },
+    // a uniformly random move with probability epsilon, the best scored otherwise
+    EpsilonGreedy { epsilon: f32 },
+}
+
+impl Default for FeatureWeights {
+    fn default() -> Self {
+        Self {
+            promotion: 2.0,
+            safe: 1.5,
+            leaves_back_rank: -0.5,
+            capture_length: 1.0,
+        }
+    }
+}
+
+impl FeatureWeights {
+    // how promising `action` looks on `board`, higher is better
+    pub fn score(&self, board: &Board, action: Action) -> f32 {
+        let (x, y, nx, ny) = match action {
+            Action::Move(x, y, nx, ny) => (x, y, nx, ny),
+            Action::Capture(x, y, nx, ny, _, _) => (x, y, nx, ny),
+        };
+        let (color, king) = match board.get_piece(x, y) {
+            Some(Piece::Filled(color, king)) => (color, king),
+            _ => return 0.0,
+        };
+        let piece = Piece::Filled(color, king);
+        let back_row = match color {
+            Color::Black => 0,
+            Color::Red => 7,
+        };
+
+        let mut after = board.clone();
+        after.execute_action(action);
+        let mut score = 0.0;
+        if !king && ny == piece.king_y_con() {
+            score += self.promotion;
+        }
+        if !after.is_attacked(nx, ny) {
+            score += self.safe;
+        }
+        if !king && y == back_row {
+            score += self.leaves_back_rank;
+        }
+        if let Action::Capture(..) = action {
+            let length = 1 + after.longest_capture_from(nx, ny);
+            score += self.capture_length * length as f32;
+        }
+        score
+    }
+}
+
+impl HeuristicRollout {
+    pub fn new(choice: RolloutChoice) -> Self {
+        Self {
+            weights: FeatureWeights::default(),
+            choice,
+        }
+    }
+}
+
+impl RolloutPolicy for UniformRollout {
+    fn choose(&self, _board: &Board, moves: &MoveMemHandler, rng: &mut SmallRng) -> Action {
+        moves.get_random_move(rng)
+    }
+}
+
+impl RolloutPolicy for DecisiveRollout {
+    fn choose(&self, board: &Board, moves: &MoveMemHandler, rng: &mut SmallRng) -> Action {
+        let us = board.get_current_color();
+        let mut replies = MoveMemHandler::new();
+        let mut safe = MoveMemHandler::new();
+        for i in 0..moves.len() {
+            let action = moves.get(i);
+            let mut after = board.clone();
+            after.execute_action(action);
+            if loses_now(&after, us.opposite()) {
+                return action;
+            }
+            if self.anti_decisive && !has_decisive_move(&after, us.opposite(), &mut replies) {
+                safe.add_action(action);
+            }
+        }
+        if self.anti_decisive && safe.has_actions() && safe.len() < moves.len() {
+            return self.inner.choose(board, &safe, rng);
+        }
+        self.inner.choose(board, moves, rng)
+    }
+}
+
+// whether `side` has lost on `board`. A capture does not pass the turn, so when
+// `side` is not to move only taking its last piece decides the game; a blocked
+// side may be freed before its turn comes.
+fn loses_now(board: &Board, side: Color) -> bool {
+    if board.get_current_color() == side {
+        !board.can_move(side)
+    } else {
+        !board.has_pieces(side)
+    }
+}
+
+// whether `side` is to move on `board` and has a decisive move
+fn has_decisive_move(board: &Board, side: Color, scratch: &mut MoveMemHandler) -> bool {
+    if board.get_current_color() != side {
+        return false;
+    }
+    board.get_all_actions(scratch);
+    let actions = scratch.actions();
+    (0..actions.len()).any(|i| {
+        let mut after = board.clone();
+        after.execute_action(actions.get(i));
+        loses_now(&after, side.opposite())
+    })
+}
+
+impl RolloutPolicy for HeuristicRollout {
+    fn choose(&self, board: &Board, moves: &MoveMemHandler, rng: &mut SmallRng) -> Action {
+        if moves.len() == 1 {
+            return moves.get(0);
+        }
+        if let RolloutChoice::EpsilonGreedy { epsilon } = self.choice {
+            if rng.gen::<f32>() < epsilon {
+                return moves.get_random_move(rng);
+            }
+        }
+
+        let mut scores = StaticList::new();
+        let mut best = f32::NEG_INFINITY;
+        for i in 0..moves.len() {
+            let score = self.weights.score(board, moves.get(i));
+            best = best.max(score);
+            scores.push(score);
+        }
+
+        match self.choice {
+            RolloutChoice::Softmax { temperature } => {
+                // relative to the best score so exp stays finite
+                let weight = |score: f32| ((score - best) / temperature.max(1e-3)).exp();
+                let total: f32 = (0..scores.len()).map(|i| weight(scores.get(i))).sum();
+                let mut pick = rng.gen::<f32>() * total;
+                for i in 0..scores.len() {
+                    pick -= weight(scores.get(i));
+                    if pick <= 0.0 {
+                        return moves.get(i);
+                    }
+                }
+                moves.get(scores.len() - 1)
+            }
+            RolloutChoice::EpsilonGreedy { .. } => {
+                // ties are broken at random so equal moves still vary between playouts
+                let tied = (0..scores.len()).filter(|&i| scores.get(i) == best);
+                let count = tied.clone().count();
+                let index = tied.clone().nth(rng.gen_range(0..count)).unwrap();
+                moves.get(index)
+            }
+        }
+    }
+}
+
+// How a playout ended, as Black's share of the win: 1 or 0 when it was played
+// to the end, in between when it was cut off and scored by the evaluation.
+#[derive(Clone, Copy, Debug)]
+pub struct Outcome {
+    black: f32,
+}
+
+impl Outcome {
+    pub fn win(winner: Color) -> Self {
+        Self::estimate(winner, 1.0)
+    }
+
+    // `color` wins with probability `chance`
+    pub fn estimate(color: Color, chance: f32) -> Self {
+        match color {
+            Color::Black => Self { black: chance },
+            Color::Red => Self {
+                black: 1.0 - chance,
+            },
+        }
+    }
+
+    // the share of the win that goes to `color`
+    pub fn value_for(&self, color: Color) -> f32 {
+        match color {
+            Color::Black => self.black,
+            Color::Red => 1.0 - self.black,
+        }
+    }
+}
+
+// Plays `board` out with `policy`. With `max_plies` set, a playout still going
+// after that many moves is stopped and the position is scored by the static
+// evaluation instead, mapped to a win probability for the side to move.
+// Every move played is appended to `trace` with the side that played it.
+pub fn play_out(
+    board: &mut Board,
+    policy: &dyn RolloutPolicy,
+    max_plies: Option<usize>,
+    move_mem: &mut MoveMemHandler,
+    rng: &mut SmallRng,
+    mut trace: Option<&mut Vec<(Color, Action)>>,
+) -> Outcome {
+    let mut plies = 0;
+    loop {
+        board.get_all_actions(move_mem);
+        if !move_mem.has_actions() {
+            return Outcome::win(board.get_current_color().opposite());
+        }
+        if max_plies.is_some_and(|max| plies >= max) {
+            let chance = win_chance(minimax::evaluate(board));
+            return Outcome::estimate(board.get_current_color(), chance);
+        }
+        let action = policy.choose(board, move_mem, rng);
+        if let Some(trace) = trace.as_mut() {
+            trace.push((board.get_current_color(), action));
+        }
+        board.execute_action(action);
+        plies += 1;
+    }
+}
+
+// a score for the side to move turned into its chance of winning
+pub fn win_chance(score: i32) -> f32 {
+    1.0 / (1.0 + (-(score as f32) / EVALUATION_SCALE).exp())
+}
+
+// "uniform", "softmax", "softmax:0.5", "greedy" or "greedy:0.1", optionally
+// followed by "+decisive" or "+anti" (decisive and anti-decisive checks)
+pub fn parse(text: &str) -> Option<Arc<dyn RolloutPolicy>> {
+    if let Some((inner, checks)) = text.split_once('+') {
+        let anti_decisive = match checks {
+            "decisive" => false,
+            "anti" => true,
+            _ => return None,
+        };
+        return Some(Arc::new(DecisiveRollout {
+            inner: parse(inner)?,
+            anti_decisive,
+        }));
+    }
+    let (name, constant) = match text.split_once(':') {
+        Some((name, constant)) => (name, Some(constant.parse::<f32>().ok()?)),
+        None => (text, None),
+    };
+    match name {
+        "uniform" => Some(Arc::new(UniformRollout)),
+        "softmax" => Some(Arc::new(HeuristicRollout::new(RolloutChoice::Softmax {
+            temperature: constant.unwrap_or(1.0),
+        }))),
+        "greedy" => Some(Arc::new(HeuristicRollout::new(
+            RolloutChoice::EpsilonGreedy {
+                epsilon: constant.unwrap_or(0.1),
+            },
+        ))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taking_the_last_piece_is_decisive_though_the_turn_stays() {
+        let mut board = Board::new(Color::Black);
+        board.set_piece(2, 2, Piece::Filled(Color::Black, true));
+        board.set_piece(3, 3, Piece::Filled(Color::Red, true));
+        board.execute_action(Action::Capture(2, 2, 4, 4, 3, 3));
+        assert_eq!(board.get_current_color(), Color::Black);
+        assert!(loses_now(&board, Color::Red));
+        assert!(!loses_now(&board, Color::Black));
+    }
+
+    #[test]
+    fn a_blocked_side_has_not_lost_while_the_capturer_moves_on() {
+        let mut board = Board::new(Color::Black);
+        board.set_piece(2, 2, Piece::Filled(Color::Black, true));
+        board.set_piece(3, 3, Piece::Filled(Color::Red, true));
+        board.set_piece(1, 1, Piece::Filled(Color::Red, false));
+        board.set_piece(0, 0, Piece::Filled(Color::Black, false));
+        board.set_piece(2, 0, Piece::Filled(Color::Black, false));
+        board.execute_action(Action::Capture(2, 2, 4, 4, 3, 3));
+        assert_eq!(board.get_current_color(), Color::Black);
+        assert!(!board.can_move(Color::Red));
+        assert!(!loses_now(&board, Color::Red));
+    }
+}
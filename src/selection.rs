@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+// statistics of one child as seen by a selection policy,
+// `wins` are counted for the player who moved into the child
+#[derive(Clone, Copy, Debug)]
+pub struct ChildStats {
+    pub wins: f32,
+    pub sims: i32,
+    pub prior: f32,
+}
+
+// the parent's visit count N in the forms the policies use, computed
+// once per node and shared by the scores of all of its children
+#[derive(Clone, Copy, Debug)]
+pub struct ParentStats {
+    pub log_sims: f32,
+    pub sqrt_sims: f32,
+}
+
+// Decides which child the tree descends into during selection,
+// the child with the highest score is picked.
+pub trait SelectionPolicy: Send + Sync {
+    fn score(&self, child: &ChildStats, parent: &ParentStats) -> f32;
+}
+
+// UCB1: mean + c * sqrt(ln(N) / n)
+pub struct Ucb1 {
+    pub exploration: f32,
+}
+
+// UCB1-Tuned replaces the constant with an upper bound on each child's variance,
+// for win/loss results the variance follows from the mean
+pub struct Ucb1Tuned;
+
+// PUCT as used by AlphaZero: mean + c * prior * sqrt(N) / (1 + n)
+pub struct Puct {
+    pub exploration: f32,
+    // value assumed for children that have not been visited yet
+    pub first_play_urgency: f32,
+}
+
+// Rapid Action Value Estimation: blends a child's mean with the all-moves-as-first
+// mean of its move, i.e. the results of every simulation in which the same side
+// played the same move anywhere later on. The AMAF mean is available after a
+// few simulations but biased, so its weight beta = sqrt(k / (3n + k)) fades as
+// the child's own simulations n grow; at n = k both count about equally.
+#[derive(Clone, Copy, Debug)]
+pub struct Rave {
+    pub equivalence: f32,
+}
+
+impl Rave {
+    pub fn new(equivalence: f32) -> Self {
+        Self { equivalence }
+    }
+
+    // `child` with its wins replaced so that wins / sims is the blended mean.
+    // An unvisited child has beta = 1, it counts as one visit with the AMAF mean
+    // so that the policy scores it by that mean instead of as unvisited.
+    pub fn blend(&self, child: ChildStats, amaf_wins: f32, amaf_sims: i32) -> ChildStats {
+        if amaf_sims == 0 {
+            return child;
+        }
+        let amaf_mean = amaf_wins / amaf_sims as f32;
+        if child.sims == 0 {
+            return ChildStats {
+                wins: amaf_mean,
+                sims: 1,
+                ..child
+            };
+        }
+        let k = self.equivalence;
+        let beta = (k / (3.0 * child.sims as f32 + k)).sqrt();
+        let mean = (1.0 - beta) * child.mean() + beta * amaf_mean;
+        ChildStats {
+            wins: mean * child.sims as f32,
+            ..child
+        }
+    }
+}
+
+impl ChildStats {
+    fn mean(&self) -> f32 {
+        self.wins / self.sims as f32
+    }
+}
+
+impl ParentStats {
+    pub fn new(sims: i32) -> Self {
+        let sims = sims.max(1) as f32;
+        Self {
+            log_sims: sims.ln(),
+            sqrt_sims: sims.sqrt(),
+        }
+    }
+}
+
+impl Ucb1 {
+    pub fn new(exploration: f32) -> Self {
+        Self { exploration }
+    }
+}
+
+impl Default for Ucb1 {
+    fn default() -> Self {
+        Self::new(std::f32::consts::SQRT_2)
+    }
+}
+
+impl Puct {
+    pub fn new(exploration: f32) -> Self {
+        Self {
+            exploration,
+            first_play_urgency: 0.5,
+        }
+    }
+}
+
+impl SelectionPolicy for Ucb1 {
+    fn score(&self, child: &ChildStats, parent: &ParentStats) -> f32 {
+        if child.sims == 0 {
+            return f32::INFINITY;
+        }
+        child.mean() + self.exploration * (parent.log_sims / child.sims as f32).sqrt()
+    }
+}
+
+impl SelectionPolicy for Ucb1Tuned {
+    fn score(&self, child: &ChildStats, parent: &ParentStats) -> f32 {
+        if child.sims == 0 {
+            return f32::INFINITY;
+        }
+        let log_parent = parent.log_sims;
+        let sims = child.sims as f32;
+        let mean = child.mean();
+        let variance = (mean - mean * mean).max(0.0) + (2.0 * log_parent / sims).sqrt();
+        mean + (log_parent / sims * variance.min(0.25)).sqrt()
+    }
+}
+
+impl SelectionPolicy for Puct {
+    fn score(&self, child: &ChildStats, parent: &ParentStats) -> f32 {
+        let mean = if child.sims == 0 {
+            self.first_play_urgency
+        } else {
+            child.mean()
+        };
+        let exploration = parent.sqrt_sims / (1 + child.sims) as f32;
+        mean + self.exploration * child.prior * exploration
+    }
+}
+
+// "ucb1", "ucb1:0.7", "tuned", "puct" or "puct:2.5"
+pub fn parse(text: &str) -> Option<Arc<dyn SelectionPolicy>> {
+    let (name, constant) = match text.split_once(':') {
+        Some((name, constant)) => (name, Some(constant.parse::<f32>().ok()?)),
+        None => (text, None),
+    };
+    match name {
+        "ucb1" => Some(Arc::new(match constant {
+            Some(c) => Ucb1::new(c),
+            None => Ucb1::default(),
+        })),
+        "tuned" => Some(Arc::new(Ucb1Tuned)),
+        "puct" => Some(Arc::new(Puct::new(constant.unwrap_or(1.5)))),
+        _ => None,
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::Instant,
+};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::board::{Action, Board, Color, MoveMemHandler};
+use crate::clock::MoveTime;
+use crate::game::Player;
+use crate::montecarlo::{RootChild, SearchBudget, SearchProgress, TreeConfig};
+use crate::rollout;
+use crate::selection::{ChildStats, ParentStats};
+
+// wins are stored in fixed point so fractional results can be added atomically
+const WIN_UNIT: f32 = 65536.0;
+
+// Tree parallelism: all threads grow one shared tree.
+// Nodes never move once created, children are published once through a
+// OnceLock and the statistics are atomics, so no locks are held while searching.
+// Boards are not stored, each iteration replays the moves from the root.
+struct SharedNode {
+    action: Option<Action>,
+    // side that played `action`, wins are counted for it
+    mover: Option<Color>,
+    sims: AtomicI32,
+    wins: AtomicU64,
+    // threads currently searching below this node
+    virtual_loss: AtomicI32,
+    prior: f32,
+    children: OnceLock<Box<[SharedNode]>>,
+}
+
+pub struct SharedTree {
+    board: Board,
+    root: SharedNode,
+    config: TreeConfig,
+    nodes: AtomicUsize,
+}
+
+impl SharedNode {
+    fn new(action: Option<Action>, mover: Option<Color>, prior: f32) -> Self {
+        Self {
+            action,
+            mover,
+            sims: AtomicI32::new(0),
+            wins: AtomicU64::new(0),
+            virtual_loss: AtomicI32::new(0),
+            prior,
+            children: OnceLock::new(),
+        }
+    }
+
+    fn wins(&self) -> f32 {
+        self.wins.load(Ordering::Relaxed) as f32 / WIN_UNIT
+    }
+
+    // a thread on its way down counts as a lost simulation,
+    // which steers the other threads towards different lines
+    fn stats(&self) -> ChildStats {
+        ChildStats {
+            wins: self.wins(),
+            sims: self.sims.load(Ordering::Relaxed) + self.virtual_loss.load(Ordering::Relaxed),
+            prior: self.prior,
+        }
+    }
+}
+
+impl SharedTree {
+    pub fn new(board: Board, config: TreeConfig) -> Self {
+        Self {
+            board,
+            root: SharedNode::new(None, None, 1.0),
+            config,
+            nodes: AtomicUsize::new(1),
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed) * mem::size_of::<SharedNode>()
+    }
+
+    // runs `threads` workers on the tree until the budget is used up,
+    // returns the number of iterations made by all of them together
+    pub fn search(&self, threads: usize, seed: u64, budget: &SearchBudget) -> usize {
+        // the root is always expanded so even an empty budget leaves moves to choose from
+        self.root
+            .children
+            .get_or_init(|| self.expand(&self.board, &mut MoveMemHandler::new()));
+        let start = Instant::now();
+        let iterations = AtomicUsize::new(0);
+        let mut seeds = SmallRng::seed_from_u64(seed);
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                let mut rng = SmallRng::seed_from_u64(seeds.gen());
+                let iterations = &iterations;
+                scope.spawn(move || {
+                    let mut move_mem = MoveMemHandler::new();
+                    while !budget.exhausted(&SearchProgress {
+                        iterations: iterations.load(Ordering::Relaxed),
+                        elapsed: start.elapsed(),
+                        instability: 0.0,
+                        root_sims: self.root.sims.load(Ordering::Relaxed),
+                        memory_bytes: self.memory_usage(),
+                    }) {
+                        self.iterate(&mut move_mem, &mut rng);
+                        iterations.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        iterations.into_inner()
+    }
+
+    // one selection, expansion, playout and backpropagation
+    fn iterate(&self, move_mem: &mut MoveMemHandler, rng: &mut SmallRng) {
+        let mut board = self.board.clone();
+        let mut path = vec![&self.root];
+        let mut node = &self.root;
+
+        while let Some(children) = node.children.get() {
+            if children.is_empty() {
+                break;
+            }
+            node = self.select(node, children);
+            path.push(node);
+            board.execute_action(node.action.unwrap());
+        }
+
+        if node.children.get().is_none() {
+            let children = node.children.get_or_init(|| self.expand(&board, move_mem));
+            if !children.is_empty() {
+                node = &children[rng.gen_range(0..children.len())];
+                node.virtual_loss.fetch_add(1, Ordering::Relaxed);
+                path.push(node);
+                board.execute_action(node.action.unwrap());
+            }
+        }
+
+        let outcome = rollout::play_out(
+            &mut board,
+            &*self.config.rollout,
+            self.config.rollout_cutoff,
+            move_mem,
+            rng,
+            None,
+        );
+
+        for node in path {
+            node.sims.fetch_add(1, Ordering::Relaxed);
+            if let Some(mover) = node.mover {
+                let wins = (outcome.value_for(mover) * WIN_UNIT) as u64;
+                node.wins.fetch_add(wins, Ordering::Relaxed);
+            }
+            if node.action.is_some() {
+                node.virtual_loss.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn select<'a>(&self, parent: &SharedNode, children: &'a [SharedNode]) -> &'a SharedNode {
+        let parent = ParentStats::new(parent.stats().sims);
+        let policy = &*self.config.selection;
+        let best = children
+            .iter()
+            .max_by(|a, b| {
+                policy
+                    .score(&a.stats(), &parent)
+                    .total_cmp(&policy.score(&b.stats(), &parent))
+            })
+            .unwrap();
+        best.virtual_loss.fetch_add(1, Ordering::Relaxed);
+        best
+    }
+
+    fn expand(&self, board: &Board, move_mem: &mut MoveMemHandler) -> Box<[SharedNode]> {
+        move_mem.clear();
+        board.get_all_actions(move_mem);
+        let actions = move_mem.actions();
+        let prior = 1.0 / actions.len().max(1) as f32;
+        let mover = board.get_current_color();
+        self.nodes.fetch_add(actions.len(), Ordering::Relaxed);
+        (0..actions.len())
+            .map(|i| SharedNode::new(Some(actions.get(i)), Some(mover), prior))
+            .collect()
+    }
+
+    pub fn root_children(&self) -> Vec<RootChild> {
+        let mut children: Vec<RootChild> = self
+            .root
+            .children
+            .get()
+            .map(|children| children.iter())
+            .into_iter()
+            .flatten()
+            .map(|child| RootChild {
+                action: child.action.unwrap(),
+                sims: child.sims.load(Ordering::Relaxed),
+                wins: child.wins(),
+            })
+            .collect();
+        children.sort_by_key(|child| std::cmp::Reverse(child.sims));
+        children
+    }
+}
+
+pub struct TreeParallelPlayer {
+    config: TreeConfig,
+    threads: usize,
+    rng: SmallRng,
+}
+
+impl TreeParallelPlayer {
+    pub fn new(config: TreeConfig, threads: usize, seed: u64) -> Self {
+        Self {
+            config,
+            threads,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for TreeParallelPlayer {
+    fn choose_move(&mut self, board: &Board, time: MoveTime) -> Action {
+        let tree = SharedTree::new(board.clone(), self.config.clone());
+        tree.search(self.threads, self.rng.gen(), &SearchBudget::time(time));
+        tree.root_children()[0].action
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::board::{Action, Board, Color, MoveMemHandler, PackedAction, Piece};
+use crate::game::{self, MAX_GAME_PLIES};
+use crate::montecarlo::{RootChild, SearchBudget, Tree, TreeConfig};
+
+const SHARD_MAGIC: &[u8; 4] = b"CKSD";
+const SHARD_VERSION: u8 = 1;
+
+// how self-play games for training data are played
+#[derive(Clone)]
+pub struct SelfPlayConfig {
+    pub tree: TreeConfig,
+    // expansions searched for every move
+    pub iterations: usize,
+    // uniformly random moves at the start of each game, not recorded,
+    // so the games start from many different positions
+    pub opening_plies: usize,
+    // moves after the opening that are drawn in proportion to their visits
+    // instead of picked by the final move strategy
+    pub sampling_plies: usize,
+    pub threads: usize,
+}
+
+// One searched position. Values are for the side to move.
+pub struct Sample {
+    pub board: Board,
+    // win rate of the search at the root, from 0 to 1
+    pub value: f32,
+    // how the game ended: 1 won, 0 drawn, -1 lost
+    pub result: i8,
+    // share of the root's visits that went to each move, moves without visits left out
+    pub policy: Vec<(Action, f32)>,
+}
+
+// totals of a `generate` run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Generated {
+    pub games: usize,
+    pub positions: usize,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        Self {
+            tree: TreeConfig::default(),
+            iterations: 400,
+            opening_plies: 4,
+            sampling_plies: 8,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+// Plays `games` games on `config.threads` threads, each writing its positions
+// to its own shard <dir>/shard-<thread>.bin, after removing the shards of an
+// earlier run. Game g is played by thread g % threads from a seed of its own,
+// so the same seed plays the same games on any number of threads, but the
+// shards only come out identical for the same seed and the same `threads`.
+// `on_game` is called with the number and positions of every finished game.
+pub fn generate(
+    config: &SelfPlayConfig,
+    games: usize,
+    dir: &Path,
+    seed: u64,
+    on_game: impl Fn(usize, usize) + Sync,
+) -> io::Result<Generated> {
+    fs::create_dir_all(dir)?;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.starts_with("shard-") && name.ends_with(".bin") {
+            fs::remove_file(&path)?;
+        }
+    }
+    let threads = config.threads.clamp(1, games.max(1));
+    let results: Vec<io::Result<Generated>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread_index| {
+                let path = dir.join(format!("shard-{:03}.bin", thread_index));
+                let on_game = &on_game;
+                scope.spawn(move || {
+                    let mut shard = ShardWriter::create(&path)?;
+                    let mut generated = Generated::default();
+                    for game_index in (thread_index..games).step_by(threads) {
+                        let game_seed =
+                            seed ^ (game_index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                        let samples = play_game(config, game_seed);
+                        for sample in &samples {
+                            shard.write(sample)?;
+                        }
+                        on_game(game_index + 1, samples.len());
+                        generated.games += 1;
+                        generated.positions += samples.len();
+                    }
+                    shard.finish()?;
+                    Ok(generated)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("self-play thread panicked"))
+            .collect()
+    });
+
+    let mut total = Generated::default();
+    for generated in results {
+        let generated = generated?;
+        total.games += generated.games;
+        total.positions += generated.positions;
+    }
+    Ok(total)
+}
+
+// one game, every position after the random opening becomes a sample
+fn play_game(config: &SelfPlayConfig, seed: u64) -> Vec<Sample> {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut board = game::starting_board();
+    let mut move_mem = MoveMemHandler::new();
+    let mut plies = 0;
+    while plies < config.opening_plies {
+        board.get_all_actions(&mut move_mem);
+        if !move_mem.has_actions() {
+            break;
+        }
+        board.execute_action(move_mem.get_random_move(&mut rng));
+        plies += 1;
+    }
+
+    let mut samples: Vec<Sample> = Vec::new();
+    // the tree is carried from one move to the next
+    let mut tree: Option<Tree> = None;
+    let winner = loop {
+        if plies >= MAX_GAME_PLIES {
+            break None;
+        }
+        board.get_all_actions(&mut move_mem);
+        if !move_mem.has_actions() {
+            break Some(board.get_current_color().opposite());
+        }
+
+        let mut search = tree
+            .take()
+            .unwrap_or_else(|| Tree::with_config(board.clone(), rng.gen(), config.tree.clone()));
+        search.search(&SearchBudget::iterations(config.iterations));
+        let children = search.root_children();
+        let action = if samples.len() < config.sampling_plies {
+            sample_by_visits(&children, &mut rng)
+        } else {
+            search.select_best_move()
+        };
+        samples.push(Sample {
+            board: board.clone(),
+            value: root_value(&children),
+            result: 0,
+            policy: visit_shares(&children),
+        });
+
+        board.execute_action(action);
+        if search.advance(action) {
+            tree = Some(search);
+        }
+        plies += 1;
+    };
+
+    if let Some(winner) = winner {
+        for sample in &mut samples {
+            sample.result = if sample.board.get_current_color() == winner {
+                1
+            } else {
+                -1
+            };
+        }
+    }
+    samples
+}
+
+fn sample_by_visits(children: &[RootChild], rng: &mut SmallRng) -> Action {
+    let total: i32 = children.iter().map(|child| child.sims).sum();
+    if total == 0 {
+        return children[rng.gen_range(0..children.len())].action;
+    }
+    let mut pick = rng.gen_range(0..total);
+    for child in children {
+        if pick < child.sims {
+            return child.action;
+        }
+        pick -= child.sims;
+    }
+    children[0].action
+}
+
+// the win rate over all simulations at the root, for the side to move there
+fn root_value(children: &[RootChild]) -> f32 {
+    let sims: i32 = children.iter().map(|child| child.sims).sum();
+    let wins: f32 = children.iter().map(|child| child.wins).sum();
+    if sims == 0 {
+        0.5
+    } else {
+        wins / sims as f32
+    }
+}
+
+fn visit_shares(children: &[RootChild]) -> Vec<(Action, f32)> {
+    let total: i32 = children.iter().map(|child| child.sims).sum();
+    children
+        .iter()
+        .filter(|child| child.sims > 0)
+        .map(|child| (child.action, child.sims as f32 / total as f32))
+        .collect()
+}
+
+// the 32 dark squares in the order they are stored
+fn dark_squares() -> impl Iterator<Item = (usize, usize)> {
+    (0..8).flat_map(|y| (y % 2..8).step_by(2).map(move |x| (x, y)))
+}
+
+// Shards start with "CKSD" and a version byte, followed by the samples:
+//   16 bytes  the 32 dark squares row by row, 4 bits each (low nibble first):
+//             0 empty, 1 black man, 2 black king, 3 red man, 4 red king
+//   1 byte    side to move, 0 black, 1 red
+//   1 byte    result as i8
+//   4 bytes   value as f32
+//   1 byte    number of policy entries, then for each:
+//             2 bytes packed action, 2 bytes visit share in 1/65535ths
+// Numbers are little endian.
+pub struct ShardWriter {
+    file: BufWriter<fs::File>,
+}
+
+impl ShardWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        file.write_all(SHARD_MAGIC)?;
+        file.write_all(&[SHARD_VERSION])?;
+        Ok(Self { file })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut squares = [0u8; 16];
+        for (index, (x, y)) in dark_squares().enumerate() {
+            let code = match sample.board.get_piece(x, y) {
+                Some(Piece::Filled(Color::Black, king)) => 1 + king as u8,
+                Some(Piece::Filled(Color::Red, king)) => 3 + king as u8,
+                _ => 0,
+            };
+            squares[index / 2] |= code << (4 * (index % 2));
+        }
+        self.file.write_all(&squares)?;
+        let side = match sample.board.get_current_color() {
+            Color::Black => 0,
+            Color::Red => 1,
+        };
+        self.file.write_all(&[side, sample.result as u8])?;
+        self.file.write_all(&sample.value.to_le_bytes())?;
+        self.file.write_all(&[sample.policy.len() as u8])?;
+        for &(action, share) in &sample.policy {
+            self.file.write_all(&action.pack().bits().to_le_bytes())?;
+            let share = (share.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+            self.file.write_all(&share.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// reads the samples of a shard one at a time
+pub struct ShardReader {
+    file: BufReader<fs::File>,
+}
+
+impl ShardReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(fs::File::open(path)?);
+        let mut header = [0u8; 5];
+        file.read_exact(&mut header)?;
+        if &header[..4] != SHARD_MAGIC || header[4] != SHARD_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is not a version {} shard",
+                    path.display(),
+                    SHARD_VERSION
+                ),
+            ));
+        }
+        Ok(Self { file })
+    }
+
+    // None at the end of the shard
+    fn read_sample(&mut self) -> io::Result<Option<Sample>> {
+        let mut squares = [0u8; 16];
+        // a clean end of file can only come before a sample
+        let read = self.file.read(&mut squares)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.file.read_exact(&mut squares[read..])?;
+        let mut fixed = [0u8; 7];
+        self.file.read_exact(&mut fixed)?;
+
+        let side = if fixed[0] == 0 {
+            Color::Black
+        } else {
+            Color::Red
+        };
+        let mut board = Board::new(side);
+        for (index, (x, y)) in dark_squares().enumerate() {
+            let piece = match squares[index / 2] >> (4 * (index % 2)) & 0xf {
+                0 => Piece::Empty,
+                code @ 1..=2 => Piece::Filled(Color::Black, code == 2),
+                code @ 3..=4 => Piece::Filled(Color::Red, code == 4),
+                code => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad square code {}", code),
+                    ))
+                }
+            };
+            board.set_piece(x, y, piece);
+        }
+
+        let mut policy = Vec::with_capacity(fixed[6] as usize);
+        for _ in 0..fixed[6] {
+            let mut entry = [0u8; 4];
+            self.file.read_exact(&mut entry)?;
+            let action = PackedAction::from_bits(u16::from_le_bytes([entry[0], entry[1]]));
+            let share = u16::from_le_bytes([entry[2], entry[3]]) as f32 / u16::MAX as f32;
+            policy.push((action.unpack(), share));
+        }
+        Ok(Some(Sample {
+            board,
+            value: f32::from_le_bytes([fixed[2], fixed[3], fixed[4], fixed[5]]),
+            result: fixed[1] as i8,
+            policy,
+        }))
+    }
+}
+
+impl Iterator for ShardReader {
+    type Item = io::Result<Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_sample().transpose()
+    }
+}
+
+// the shards written by `generate` into `dir`, in thread order
+pub fn shards_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut shards: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bin"))
+        .collect();
+    shards.sort();
+    Ok(shards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_shard_reads_back_what_was_written() {
+        let mut board = game::starting_board();
+        let mut move_mem = MoveMemHandler::new();
+        board.get_all_actions(&mut move_mem);
+        let first = move_mem.get(0);
+        let second = move_mem.get(1);
+        board.execute_action(first);
+        let samples = [
+            Sample {
+                board: game::starting_board(),
+                value: 0.625,
+                result: 1,
+                policy: vec![(first, 0.75), (second, 0.25)],
+            },
+            Sample {
+                board,
+                value: 0.5,
+                result: -1,
+                policy: Vec::new(),
+            },
+        ];
+
+        let path = std::env::temp_dir().join(format!("shard-test-{}.bin", std::process::id()));
+        let mut writer = ShardWriter::create(&path).unwrap();
+        for sample in &samples {
+            writer.write(sample).unwrap();
+        }
+        writer.finish().unwrap();
+        let read: Vec<Sample> = ShardReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.len(), samples.len());
+        for (read, written) in read.iter().zip(&samples) {
+            assert!(read.board == written.board);
+            assert_eq!(read.value, written.value);
+            assert_eq!(read.result, written.result);
+            assert_eq!(read.policy.len(), written.policy.len());
+            for (&(action, share), &(expected, expected_share)) in
+                read.policy.iter().zip(&written.policy)
+            {
+                assert_eq!(action, expected);
+                // shares are stored in 1/65535ths
+                assert!((share - expected_share).abs() < 1e-4);
+            }
+        }
+    }
+}